@@ -1,8 +1,10 @@
 use crate::{Memory, DropFn};
-use crate::droplist::{DropList, DropListWriteResult, DropItem};
+use crate::droplist::{DropList, DropListWriteResult, DropItem, DropHook};
 use std::ptr::{null_mut};
 use crate::block::{Block, PlacementError};
 use std::fmt::Debug;
+#[cfg(feature = "diagnostics")]
+use crate::WeakHolder;
 
 /// Error while trying to place data in arena block.
 #[derive(Debug)]
@@ -55,12 +57,151 @@ impl std::fmt::Display for UploadError {
 
 impl std::error::Error for UploadError {}
 
+/// Maximum number of drop phases in a single `Arena`.
+pub const MAX_DROP_PHASES: usize = 8;
+
+const DEFAULT_DROP_PHASE_ORDER: [u8; MAX_DROP_PHASES] = [0, 1, 2, 3, 4, 5, 6, 7];
+
+/// Drop phase of the values in arena.
+///
+/// Every phase has its own drop chain, and the phases are executed one after another when the
+/// arena is dropped. By default, the phases are executed in ascending order, starting with
+/// `DropPhase::DEFAULT`, which is used by all uploads that do not specify the phase.
+/// The order can be changed with `Arena::set_drop_phase_order`.
+///
+/// For example, phase 0 may close all sockets, phase 1 may drop buffers, and phase 2 may drop loggers.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct DropPhase(u8);
+
+impl DropPhase {
+    /// Phase used by uploads that do not specify the phase.
+    pub const DEFAULT: DropPhase = DropPhase(0);
+
+    /// Returns the phase with the specified index, if it is below `MAX_DROP_PHASES`.
+    pub const fn new(index: u8) -> Option<DropPhase> {
+        if (index as usize) < MAX_DROP_PHASES {
+            Some(DropPhase(index))
+        } else {
+            None
+        }
+    }
+
+    /// Index of this phase.
+    pub const fn index(&self) -> u8 {
+        self.0
+    }
+}
+
+impl Default for DropPhase {
+    fn default() -> Self {
+        DropPhase::DEFAULT
+    }
+}
+
+/// First and last droplists of a single drop phase, null if nothing was placed in this phase.
+#[derive(Copy, Clone)]
+struct DropChain {
+    first: *mut DropList,
+    last: *mut DropList,
+}
+
+impl DropChain {
+    const EMPTY: DropChain = DropChain { first: null_mut(), last: null_mut() };
+}
+
+/// Number of size classes of abandoned regions. A region of `len` bytes belongs to the class
+/// `floor(log2(len))`, and the last class also keeps all larger regions.
+const FREE_REGION_CLASSES: usize = 32;
+
+/// Header written at the start of an abandoned region, linking it to the next region of the same class.
+struct FreeRegion {
+    next: *mut FreeRegion,
+    len: usize,
+}
+
+/// Abandoned arena regions that can be reused by the following allocations, by size class.
+struct FreeRegions {
+    classes: [*mut FreeRegion; FREE_REGION_CLASSES],
+    bytes: usize,
+}
+
+impl FreeRegions {
+    const EMPTY: FreeRegions = FreeRegions { classes: [null_mut(); FREE_REGION_CLASSES], bytes: 0 };
+
+    #[inline(always)]
+    fn class_containing(len: usize) -> usize {
+        (len.ilog2() as usize).min(FREE_REGION_CLASSES - 1)
+    }
+
+    /// Keeps the region for reuse. Regions that are too small or not aligned for the header are ignored.
+    unsafe fn push(&mut self, ptr: *mut u8, len: usize) {
+        if len < std::mem::size_of::<FreeRegion>() || ptr.align_offset(std::mem::align_of::<FreeRegion>()) != 0 {
+            return;
+        }
+        let class = FreeRegions::class_containing(len);
+        let region = ptr as *mut FreeRegion;
+        std::ptr::write(region, FreeRegion { next: self.classes[class], len });
+        self.classes[class] = region;
+        self.bytes += len;
+    }
+
+    /// Takes a region of at least `len` bytes aligned to `align`, if the first region of the
+    /// smallest class that is guaranteed to fit it is suitable.
+    ///
+    /// Only the head of one class is checked, so that allocation stays O(1), and a fitting region
+    /// deeper in the list or in a larger class is not found. The unused tail of the taken region
+    /// is kept for reuse.
+    unsafe fn take(&mut self, len: usize, align: usize) -> Option<*mut u8> {
+        if self.bytes == 0 || len == 0 {
+            return None;
+        }
+        let class = len.next_power_of_two().ilog2() as usize;
+        if class >= FREE_REGION_CLASSES {
+            return None;
+        }
+        let region = self.classes[class];
+        if region.is_null() || region.align_offset(align) != 0 {
+            return None;
+        }
+        let region_len = (*region).len;
+        self.classes[class] = (*region).next;
+        self.bytes -= region_len;
+
+        let ptr = region as *mut u8;
+        let tail_offset = len + ptr.add(len).align_offset(std::mem::align_of::<FreeRegion>());
+        if tail_offset < region_len {
+            self.push(ptr.add(tail_offset), region_len - tail_offset);
+        }
+        Some(ptr)
+    }
+}
+
+/// Memory held by arena, and the number of weak handles that keep it from being returned to
+/// `Memory` after the last `Arena` is dropped.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ArenaRetention {
+    /// Number of `WeakArena` references, held by handles like `UStr`, `N` or `List`.
+    pub weak_handles: usize,
+    /// Number of blocks used by arena.
+    pub blocks: usize,
+    /// Total size of blocks used by arena.
+    pub bytes: usize,
+    /// Total size of abandoned regions that can be reused by the following allocations.
+    pub reusable_bytes: usize,
+}
+
 /// Information about arena injected in first allocated arena memory block.
 struct ArenaMetadata {
     memory: Memory,
     last_block: Option<Block>,
-    first_drop_list: *mut DropList,
-    last_drop_list: *mut DropList,
+    drop_chains: [DropChain; MAX_DROP_PHASES],
+    drop_phase_order: [u8; MAX_DROP_PHASES],
+    first_drop_hook: *mut DropHook,
+    last_drop_hook: *mut DropHook,
+    background_drop: bool,
+    free_regions: FreeRegions,
+    // last generation stamp given to an item, used to detect items removed before the arena is dropped
+    last_generation: u64,
     strong_rc: i64,
     rc: i64,
 }
@@ -92,51 +233,97 @@ impl ArenaMetadata {
         trace!("dec_wk s {} t {}", self.strong_rc, self.rc);
     }
 
-    unsafe fn push_drop_fn<T>(&mut self, data: *const u8) -> Result<*const Option<DropItem>, UploadError> {
-        debug_assert_ne!(null_mut(), self.first_drop_list, "push: drop list not null (1)");
-        debug_assert_ne!(null_mut(), self.last_drop_list, "push: drop list not null (2)");
+    #[inline(always)]
+    pub fn weak_count(&self) -> usize {
+        (self.rc - self.strong_rc) as usize
+    }
+
+    unsafe fn push_drop_fn<T>(&mut self, phase: DropPhase, data: *const u8) -> Result<*const Option<DropItem>, UploadError> {
+        let last_drop_list = self.last_drop_list_in_phase(phase)?;
+        debug_assert_ne!(null_mut(), last_drop_list, "push: drop list not null (1)");
 
-        Ok(match (*self.last_drop_list).push_drop_fn::<T>(data) {
+        Ok(match (*last_drop_list).push_drop_fn::<T>(data) {
             (DropListWriteResult::ListFull, item) => {
-                self.push_next_drop_list()?;
+                self.push_next_drop_list(phase)?;
                 item
             },
             (DropListWriteResult::ListNotFull, item) => item,
         })
     }
 
-    pub unsafe fn push_custom_drop_fn(&mut self, fun: DropFn, data: *const u8) -> Result<*const Option<DropItem>, UploadError> {
-        debug_assert_ne!(null_mut(), self.first_drop_list, "push: drop list not null (3)");
-        debug_assert_ne!(null_mut(), self.last_drop_list, "push: drop list not null (4)");
+    pub unsafe fn push_custom_drop_fn(&mut self, phase: DropPhase, fun: DropFn, data: *const u8) -> Result<*const Option<DropItem>, UploadError> {
+        let last_drop_list = self.last_drop_list_in_phase(phase)?;
+        debug_assert_ne!(null_mut(), last_drop_list, "push: drop list not null (2)");
 
-        Ok(match (*self.last_drop_list).push_custom_drop_fn(fun, data) {
+        Ok(match (*last_drop_list).push_custom_drop_fn(fun, data) {
             (DropListWriteResult::ListFull, item) => {
-                self.push_next_drop_list()?;
+                self.push_next_drop_list(phase)?;
                 item
             },
             (DropListWriteResult::ListNotFull, item) => item,
         })
     }
 
-    unsafe fn push_next_drop_list(&mut self) -> Result<(), UploadError> {
+    /// Returns the last droplist of the phase, placing the first droplist if the phase is not used yet.
+    unsafe fn last_drop_list_in_phase(&mut self, phase: DropPhase) -> Result<*mut DropList, UploadError> {
+        let chain = self.drop_chains[phase.0 as usize];
+        if !chain.last.is_null() {
+            return Ok(chain.last);
+        }
+        let drop_list = match self.upload_no_drop(DropList::empty()) {
+            Ok(v) => v,
+            Err(_) => return Err(UploadError::DropListDoesNotFit),
+        };
+        self.drop_chains[phase.0 as usize] = DropChain { first: drop_list, last: drop_list };
+        Ok(drop_list)
+    }
+
+    pub fn set_drop_phase_order(&mut self, order: &[DropPhase]) {
+        let remaining = (0..MAX_DROP_PHASES as u8).map(DropPhase);
+        let mut next = 0;
+        for phase in order.iter().copied().chain(remaining) {
+            if !self.drop_phase_order[..next].contains(&phase.0) {
+                self.drop_phase_order[next] = phase.0;
+                next += 1;
+            }
+        }
+    }
+
+    /// Place hook that is executed after all drop lists.
+    pub unsafe fn push_drop_hook(&mut self, fun: DropFn, data: *const u8) -> Result<(), UploadError> {
+        let hook = self.upload_no_drop(DropHook {
+            item: DropItem { fun, data },
+            next: null_mut(),
+        })?;
+        if self.last_drop_hook.is_null() {
+            self.first_drop_hook = hook;
+        } else {
+            (*self.last_drop_hook).next = hook;
+        }
+        self.last_drop_hook = hook;
+        Ok(())
+    }
+
+    unsafe fn push_next_drop_list(&mut self, phase: DropPhase) -> Result<(), UploadError> {
         let next_drop_list = match self.upload_no_drop(DropList::empty()) {
             Ok(v) => v,
             Err(_) => return Err(UploadError::DropListDoesNotFit),
         };
-        debug_assert_ne!(self.last_drop_list, null_mut(), "last drop list not null");
-        (*self.last_drop_list).set_next_list(next_drop_list);
-        self.last_drop_list = next_drop_list;
+        let chain = &mut self.drop_chains[phase.0 as usize];
+        debug_assert_ne!(chain.last, null_mut(), "last drop list not null");
+        (*chain.last).set_next_list(next_drop_list);
+        chain.last = next_drop_list;
         Ok(())
     }
 
-    /// Place item to arena and return a pointer to it, and also add drop function to drop list to drop this
-    /// item when there are no remaining `Arena` instances.
-    pub unsafe fn upload_auto_drop<T>(&mut self, value: T) -> Result<(*mut T, *const Option<DropItem>), UploadError> {
+    /// Place item to arena and return a pointer to it, and also add drop function to drop list of the
+    /// specified phase to drop this item when there are no remaining `Arena` instances.
+    pub unsafe fn upload_auto_drop<T>(&mut self, phase: DropPhase, value: T) -> Result<(*mut T, *const Option<DropItem>), UploadError> {
         let last_block = self.last_block.as_mut().unwrap();
         match last_block.push_copy::<T>(&value) {
             Ok(value_ptr) => {
                 std::mem::forget(value);
-                let drop_item = self.push_drop_fn::<T>(value_ptr as *const u8)?;
+                let drop_item = self.push_drop_fn::<T>(phase, value_ptr as *const u8)?;
                 return Ok((value_ptr, drop_item));
             },
             Err(e) => match e {
@@ -145,14 +332,14 @@ impl ArenaMetadata {
             }
         }
 
-        let mut block = Some(Block::new(self.memory.take_block()));
+        let mut block = Some(self.take_block_of_size(0));
         std::mem::swap(&mut block, &mut self.last_block);
         let last_block = self.last_block.as_mut().unwrap();
         last_block.set_previous_block(block.unwrap());
 
         let value_ptr = last_block.push_copy::<T>(&value).ok().expect("fits into subsequent block (1)");
         std::mem::forget(value);
-        let drop_item = self.push_drop_fn::<T>(value_ptr as *const u8)?;
+        let drop_item = self.push_drop_fn::<T>(phase, value_ptr as *const u8)?;
         Ok((value_ptr, drop_item))
     }
 
@@ -170,7 +357,7 @@ impl ArenaMetadata {
             }
         }
 
-        let mut block = Some(Block::new(self.memory.take_block()));
+        let mut block = Some(self.take_block_of_size(0));
         std::mem::swap(&mut block, &mut self.last_block);
         let last_block = self.last_block.as_mut().unwrap();
         last_block.set_previous_block(block.unwrap());
@@ -189,10 +376,14 @@ impl ArenaMetadata {
         }
 
         if len > last_block.largest_item_size() {
-            return Err(UploadError::ItemDoesNotFit);
+            let ptr = self.alloc_in_dedicated_block::<u8>(len);
+            for (i, byte) in value.take(len).enumerate() {
+                *ptr.add(i) = byte;
+            }
+            return Ok(ptr);
         }
 
-        let mut block = Some(Block::new(self.memory.take_block()));
+        let mut block = Some(self.take_block_of_size(0));
         std::mem::swap(&mut block, &mut self.last_block);
         let last_block = self.last_block.as_mut().unwrap();
         last_block.set_previous_block(block.unwrap());
@@ -205,19 +396,49 @@ impl ArenaMetadata {
         unreachable!("upload_no_drop_bytes failed after acquiring the next block")
     }
 
+    /// Take a block that has at least `len` bytes from `Memory` and record it as leased by this arena.
+    fn take_block_of_size(&mut self, len: usize) -> Block {
+        let arena = self as *const ArenaMetadata as usize;
+        Block::new(self.memory.lease_block(arena, len))
+    }
+
+    /// Allocate `len` bytes aligned for `T` that are larger than a block in a dedicated block.
+    ///
+    /// The dedicated block is linked behind the last block, so that the remaining space in the last
+    /// block can still be used. It is freed instead of being returned to `Memory` when arena is dropped.
+    unsafe fn alloc_in_dedicated_block<T>(&mut self, len: usize) -> *mut T {
+        let mut block = self.take_block_of_size(Block::size_for_item(len + std::mem::align_of::<T>()));
+        let last_block = self.last_block.as_mut().unwrap();
+        if let Some(previous_block) = last_block.take_previous_block() {
+            block.set_previous_block(previous_block);
+        }
+
+        let (remaining_bytes_for_alignment, aligned_start) = block.remaining_bytes_for_alignment::<T>();
+        debug_assert!(remaining_bytes_for_alignment >= len as isize, "dedicated block fits the item");
+        let ptr = block.upload_bytes_unchecked_uninit(aligned_start, len) as *mut T;
+        last_block.set_previous_block(block);
+        ptr
+    }
+
     pub unsafe fn alloc_no_drop_items_aligned_uninit<T>(&mut self, len: usize, offset_between_items: usize) -> Result<*mut T, UploadError> {
+        let total_array_len = len.checked_mul(offset_between_items)
+            .filter(|total| *total <= isize::MAX as usize)
+            .ok_or(UploadError::ItemDoesNotFit)?;
+        if let Some(ptr) = self.free_regions.take(total_array_len, std::mem::align_of::<T>()) {
+            return Ok(ptr as *mut T);
+        }
+
         let last_block = self.last_block.as_mut().unwrap();
         let (remaining_bytes_for_alignment, aligned_start) = last_block.remaining_bytes_for_alignment::<T>();
-        let total_array_len = len * offset_between_items;
         if remaining_bytes_for_alignment >= total_array_len as isize {
             return Ok(last_block.upload_bytes_unchecked_uninit(aligned_start, total_array_len) as *mut T);
         }
 
         if total_array_len > last_block.largest_item_size() {
-            return Err(UploadError::ItemDoesNotFit);
+            return Ok(self.alloc_in_dedicated_block::<T>(total_array_len));
         }
 
-        let mut block = Some(Block::new(self.memory.take_block()));
+        let mut block = Some(self.take_block_of_size(0));
         std::mem::swap(&mut block, &mut self.last_block);
         let last_block = self.last_block.as_mut().unwrap();
         last_block.set_previous_block(block.unwrap());
@@ -230,20 +451,58 @@ impl ArenaMetadata {
         unreachable!("alloc_no_drop_items_aligned_uninit failed after acquiring the next block")
     }
 
+    /// Grows the chunk of bytes at `ptr` from `len` to `new_len` bytes if it is the last item in
+    /// the last block and the block has enough space. Returns false otherwise.
+    pub unsafe fn try_grow_in_place(&mut self, ptr: *const u8, len: usize, new_len: usize) -> bool {
+        self.last_block.as_mut().unwrap().try_resize_last_item(ptr, len, new_len)
+    }
+
+    pub unsafe fn abandon_region(&mut self, ptr: *mut u8, len: usize) {
+        self.free_regions.push(ptr, len)
+    }
+
     pub unsafe fn drop_objects(&mut self) {
-        debug_assert_ne!(null_mut(), self.first_drop_list, "drop_objects: drop list not null");
-        (*self.first_drop_list).execute_drop_chain();
-        self.first_drop_list = null_mut();
-        self.last_drop_list = null_mut();
+        debug_assert_ne!(null_mut(), self.drop_chains[DropPhase::DEFAULT.0 as usize].first, "drop_objects: drop list not null");
+        for phase in self.drop_phase_order {
+            let chain = &mut self.drop_chains[phase as usize];
+            if !chain.first.is_null() {
+                trace!("drop phase {}", phase);
+                (*chain.first).execute_drop_chain();
+            }
+            *chain = DropChain::EMPTY;
+        }
+
+        if !self.first_drop_hook.is_null() {
+            (*self.first_drop_hook).execute_hook_chain();
+            self.first_drop_hook = null_mut();
+            self.last_drop_hook = null_mut();
+        }
+    }
+
+    pub fn retention(&self) -> ArenaRetention {
+        let mut retention = ArenaRetention {
+            weak_handles: self.weak_count(),
+            blocks: 0,
+            bytes: 0,
+            reusable_bytes: self.free_regions.bytes,
+        };
+        let mut block = self.last_block.as_ref();
+        while let Some(b) = block {
+            retention.blocks += 1;
+            retention.bytes += b.size();
+            block = b.previous_block();
+        }
+        retention
     }
 
     /// After the call to this function metadata must not be used
     pub unsafe fn reclaim_memory(&mut self) {
+        let arena = self as *const ArenaMetadata as usize;
         let mut block = None;
         std::mem::swap(&mut block, &mut self.last_block);
         while block.is_some() {
             let (previous_block, data) = block.unwrap().into_previous_block_and_data();
-            self.memory.return_block(data);
+            self.memory.release_block(arena, data);
             block = previous_block;
         }
     }
@@ -261,6 +520,8 @@ impl ArenaMetadata {
 /// The `WeakArena`, like `Arena`, can not be shared between threads.
 pub struct WeakArena {
     metadata: *mut ArenaMetadata,
+    #[cfg(feature = "diagnostics")]
+    holder: u64,
 }
 
 /// `Arena` is a memory block container that executes `drop` for your objects when it goes out of scope.
@@ -289,18 +550,31 @@ pub struct Arena {
 
 impl Arena {
     pub fn new(memory: &Memory) -> Result<Arena, UploadError> {
-        let mut memory = memory.clone();
+        let mut memory = memory.to_internal();
         let mut block = Block::new(memory.take_block());
+        let block_size = block.size();
         let drop_list = unsafe { block.push(DropList::empty()) }.map_err(|_| UploadError::DropListDoesNotFit)?;
         let metadata = unsafe { block.push(ArenaMetadata {
             memory,
             last_block: None,
-            first_drop_list: drop_list,
-            last_drop_list: drop_list,
+            drop_chains: {
+                let mut chains = [DropChain::EMPTY; MAX_DROP_PHASES];
+                chains[DropPhase::DEFAULT.0 as usize] = DropChain { first: drop_list, last: drop_list };
+                chains
+            },
+            drop_phase_order: DEFAULT_DROP_PHASE_ORDER,
+            first_drop_hook: null_mut(),
+            last_drop_hook: null_mut(),
+            background_drop: false,
+            free_regions: FreeRegions::EMPTY,
+            last_generation: 0,
             strong_rc: 1,
             rc: 1
         }) }.map_err(|_| UploadError::MetadataDoesNotFit)?;
-        unsafe { (*metadata).last_block = Some(block) };
+        unsafe {
+            (*metadata).last_block = Some(block);
+            (*metadata).memory.record_lease(metadata as usize, block_size);
+        }
 
         Ok(Arena {
             metadata,
@@ -316,7 +590,19 @@ impl Arena {
     /// item when there are no remaining `Arena` instances. Result also contains a pointer to drop item that is valid while arena is alive.
     #[inline(always)]
     pub unsafe fn upload_auto_drop<T>(&self, value: T) -> Result<(*mut T, *const Option<DropItem>), UploadError> {
-        self.md().upload_auto_drop::<T>(value)
+        self.md().upload_auto_drop::<T>(DropPhase::DEFAULT, value)
+    }
+
+    /// Place item to arena and return a pointer to it, and also add drop function to the drop list of
+    /// the specified phase, so that this item is dropped together with other items of the same phase.
+    ///
+    /// # Safety
+    ///
+    /// The returned pointers are valid only while the arena is alive, and the item must not be
+    /// dropped or moved out by the caller, because the arena drops it.
+    #[inline(always)]
+    pub unsafe fn upload_in_phase<T>(&self, phase: DropPhase, value: T) -> Result<(*mut T, *const Option<DropItem>), UploadError> {
+        self.md().upload_auto_drop::<T>(phase, value)
     }
 
     /// Place item to arena and return a pointer to it, without adding a drop function.
@@ -337,24 +623,160 @@ impl Arena {
         self.md().alloc_no_drop_items_aligned_uninit::<T>(len, offset_between_items)
     }
 
+    /// Grows the chunk of bytes at `ptr` from `len` to `new_len` bytes if it was the last
+    /// allocation in arena and the block has enough space. Returns false otherwise.
+    ///
+    /// # Safety
+    ///
+    /// The `ptr` and `len` must describe a region placed with `alloc_no_drop_items_aligned_uninit`
+    /// or grown with this function, and the region must still be in use.
+    #[inline(always)]
+    pub unsafe fn try_grow_in_place(&self, ptr: *const u8, len: usize, new_len: usize) -> bool {
+        self.md().try_grow_in_place(ptr, len, new_len)
+    }
+
+    /// Give back `len` items at `ptr` that were placed with `alloc_no_drop_items_aligned_uninit`
+    /// and are no longer used, so that the following allocations of similar size can reuse them.
+    ///
+    /// The items are not dropped.
+    ///
+    /// # Safety
+    ///
+    /// The region must be a part of a region placed in this arena with `alloc_no_drop_items_aligned_uninit`,
+    /// no drop function may access it later, and the memory must not be accessed after this call.
+    #[inline(always)]
+    pub unsafe fn abandon_no_drop_items<T>(&self, ptr: *mut T, len: usize, offset_between_items: usize) {
+        self.md().abandon_region(ptr as *mut u8, len * offset_between_items)
+    }
+
     /// Place custom drop function that will be executed on arena drop.
     ///
     /// The data pointer should point to a memory location inside the arena.
     #[inline(always)]
     pub unsafe fn push_custom_drop_fn(&self, fun: DropFn, data: *const u8) -> Result<*const Option<DropItem>, UploadError> {
-        self.md().push_custom_drop_fn(fun, data)
+        self.md().push_custom_drop_fn(DropPhase::DEFAULT, fun, data)
+    }
+
+    /// Place custom drop function that will be executed on arena drop, in the specified phase.
+    ///
+    /// # Safety
+    ///
+    /// The data pointer should point to a memory location inside the arena, and the function must
+    /// be safe to call with it once, when the drop functions of the phase are executed.
+    #[inline(always)]
+    pub unsafe fn push_custom_drop_fn_in_phase(&self, phase: DropPhase, fun: DropFn, data: *const u8) -> Result<*const Option<DropItem>, UploadError> {
+        self.md().push_custom_drop_fn(phase, fun, data)
+    }
+
+    /// Change the order in which the drop phases are executed.
+    ///
+    /// The listed phases are executed first, in the listed order, followed by the remaining
+    /// phases in ascending order.
+    pub fn set_drop_phase_order(&self, order: &[DropPhase]) {
+        unsafe { self.md().set_drop_phase_order(order) }
+    }
+
+    /// Store the closure in arena and run it as part of the drop chain, in the same order
+    /// as the drop functions of the values uploaded to this arena.
+    pub fn defer<F>(&self, fun: F) -> Result<(), UploadError> where F: FnOnce() + 'static {
+        unsafe {
+            let fun_ptr = self.md().upload_no_drop(fun)?;
+            self.md().push_custom_drop_fn(DropPhase::DEFAULT, run_closure::<F>, fun_ptr as *const u8)?;
+        }
+        Ok(())
+    }
+
+    /// Store the closure in arena and run it when the last `Arena` is dropped, after the
+    /// drop functions for all values were executed, but before the memory is reclaimed.
+    ///
+    /// Hooks run in the order they were added.
+    pub fn on_drop<F>(&self, fun: F) -> Result<(), UploadError> where F: FnOnce() + 'static {
+        unsafe {
+            let fun_ptr = self.md().upload_no_drop(fun)?;
+            self.md().push_drop_hook(run_closure::<F>, fun_ptr as *const u8)?;
+        }
+        Ok(())
+    }
+
+    /// Enable or disable dropping of arena objects on a background thread.
+    ///
+    /// When enabled, and the last `Arena` is dropped while there are no remaining `WeakArena`
+    /// references, the drop functions are executed and the memory is returned back to `Memory` on
+    /// a background thread. Use `Memory::pending_teardowns` or `Memory::wait_for_teardowns` to
+    /// track this work. If there are remaining `WeakArena` references, the drop functions are
+    /// executed on the current thread as usual.
+    ///
+    /// # Safety
+    ///
+    /// All values with drop functions in this arena, including deferred closures and drop hooks,
+    /// must be safe to drop on another thread, in other words, they must be `Send`.
+    pub unsafe fn set_background_drop(&self, enabled: bool) {
+        self.md().background_drop = enabled;
+    }
+
+    /// Returns a new generation stamp, unique in this arena. Stamps start from 1, so 0 can
+    /// be used to mark an item that was removed.
+    #[inline(always)]
+    pub (crate) fn next_generation(&self) -> u64 {
+        let md = unsafe { self.md() };
+        md.last_generation += 1;
+        md.last_generation
+    }
+
+    /// Returns the memory held by this arena, and the number of weak handles that would keep
+    /// it from being returned to `Memory` if the arena was dropped now.
+    pub fn retention(&self) -> ArenaRetention {
+        unsafe { self.md().retention() }
+    }
+
+    /// Returns the number of `Arena` references, including the ones held by guards.
+    pub fn strong_count(&self) -> usize {
+        unsafe { self.md().strong_rc as usize }
+    }
+
+    /// Returns the number of `WeakArena` references, held by handles like `UStr`, `N` or `List`.
+    pub fn weak_count(&self) -> usize {
+        unsafe { self.md().weak_count() }
+    }
+
+    /// Returns the `WeakArena` references of this arena that are still alive, together with the
+    /// handle type and location where they were created.
+    #[cfg(feature = "diagnostics")]
+    pub fn weak_holders(&self) -> Vec<WeakHolder> {
+        unsafe { self.md().memory.weak_holders_of(self.metadata as usize) }
     }
 
     /// Clone as `WeakArena`.
+    #[track_caller]
     pub fn to_weak_arena(&self) -> WeakArena {
+        self.to_weak_arena_for::<WeakArena>()
+    }
+
+    /// Clone as `WeakArena` held by the handle of type `H`. With "diagnostics" feature, the handle
+    /// type and the caller location are recorded until the `WeakArena` is dropped.
+    #[track_caller]
+    #[cfg_attr(not(feature = "diagnostics"), allow(clippy::extra_unused_type_parameters))]
+    pub (crate) fn to_weak_arena_for<H>(&self) -> WeakArena where H: ?Sized {
         trace!("split weak arena");
         unsafe { self.md().inc_weak() };
         WeakArena {
             metadata: self.metadata,
+            #[cfg(feature = "diagnostics")]
+            holder: unsafe { self.md().memory.register_weak_holder(WeakHolder {
+                arena: self.metadata as usize,
+                handle_type: std::any::type_name::<H>(),
+                location: std::panic::Location::caller(),
+            }) },
         }
     }
 }
 
+/// Moves the closure out of arena memory and runs it.
+unsafe fn run_closure<F>(data: *const u8) where F: FnOnce() {
+    let fun = std::ptr::read(data as *const F);
+    fun();
+}
+
 impl WeakArena {
     /// Returns true if drop functions for the arena structures were not yet executed (the `Arena` is not dropped).
     #[inline(always)]
@@ -372,7 +794,7 @@ impl WeakArena {
     #[inline(always)]
     pub unsafe fn upload_auto_drop<T>(&self, value: T) -> Result<(*mut T, *const Option<DropItem>), UploadError> {
         if self.is_alive() {
-            Ok(self.md().upload_auto_drop::<T>(value)?)
+            Ok(self.md().upload_auto_drop::<T>(DropPhase::DEFAULT, value)?)
         } else {
             Err(UploadError::ArenaIsNotAlive)
         }
@@ -398,6 +820,18 @@ impl WeakArena {
         }
     }
 
+    /// Returns true if both references point to the same arena.
+    #[inline(always)]
+    pub fn is_same_arena(&self, other: &WeakArena) -> bool {
+        self.metadata == other.metadata
+    }
+
+    /// Returns true if this reference points to the `arena`.
+    #[inline(always)]
+    pub fn belongs_to(&self, arena: &Arena) -> bool {
+        self.metadata == arena.metadata
+    }
+
     /// Try to upgrade `WeakArena` to `Arena`.
     pub fn arena(&self) -> Option<Arena> {
         if self.is_alive() {
@@ -412,6 +846,25 @@ impl WeakArena {
     }
 }
 
+/// Arena metadata that is moved to a background thread to execute drop functions and reclaim memory.
+struct ArenaTeardown {
+    metadata: *mut ArenaMetadata,
+}
+
+// Arena owner promised that all objects can be dropped on another thread
+// in `Arena::set_background_drop`, and no other references to metadata remain.
+unsafe impl Send for ArenaTeardown {}
+
+impl ArenaTeardown {
+    fn run(self) {
+        trace!("drop arena objects in background");
+        unsafe {
+            (*self.metadata).drop_objects();
+            (*self.metadata).reclaim_memory();
+        }
+    }
+}
+
 impl Clone for Arena {
     fn clone(&self) -> Self {
         trace!("clone arena");
@@ -424,12 +877,19 @@ impl Clone for Arena {
 }
 
 impl Clone for WeakArena {
+    #[track_caller]
     fn clone(&self) -> Self {
         trace!("clone weak");
         let metadata = self.metadata;
         unsafe { (*metadata).inc_weak(); }
         WeakArena {
             metadata,
+            #[cfg(feature = "diagnostics")]
+            holder: unsafe { (*metadata).memory.register_weak_holder(WeakHolder {
+                arena: metadata as usize,
+                handle_type: (*metadata).memory.weak_holder_type(self.holder),
+                location: std::panic::Location::caller(),
+            }) },
         }
     }
 }
@@ -441,6 +901,13 @@ impl Drop for Arena {
         let metadata = unsafe { self.md() };
         (*metadata).dec_rc();
 
+        if metadata.strong_rc == 0 && metadata.rc == 0 && metadata.background_drop {
+            let memory = metadata.memory.clone();
+            let teardown = ArenaTeardown { metadata: self.metadata };
+            memory.spawn_teardown(Box::new(move || teardown.run()));
+            return;
+        }
+
         if (*metadata).strong_rc == 0 {
             trace!("drop arena objects");
             unsafe { (*metadata).drop_objects() };
@@ -459,6 +926,8 @@ impl Drop for WeakArena {
         trace!("drop weak arena");
 
         let metadata = unsafe { self.md() };
+        #[cfg(feature = "diagnostics")]
+        metadata.memory.unregister_weak_holder(self.holder);
         (*metadata).dec_weak();
 
         if (*metadata).rc == 0 {
@@ -471,7 +940,7 @@ impl Drop for WeakArena {
 
 #[cfg(test)]
 mod arena_tests {
-    use crate::{Memory, Arena, N};
+    use crate::{Memory, Arena, N, DropPhase};
     use crate::dropflag::DropFlag;
     use std::cell::RefCell;
 
@@ -515,15 +984,15 @@ mod arena_tests {
             let mut obj = N::new(&arena, Compact { value: flag.clone() }).unwrap();
 
             assert_eq!(1, *(*flag).borrow(), "drop was not called");
-            assert_ne!(None, obj.val(), "value can be accessed");
-            assert_ne!(None, obj.var(), "value can be accessed");
+            assert!(obj.val().is_some(), "value can be accessed");
+            assert!(obj.var().is_some(), "value can be accessed");
 
             obj
         };
 
         assert_eq!(0, *(*flag).borrow(), "drop was called");
-        assert_eq!(None, obj.val(), "value can not be accessed");
-        assert_eq!(None, obj.var(), "value can not be accessed");
+        assert!(obj.val().is_none(), "value can not be accessed");
+        assert!(obj.var().is_none(), "value can not be accessed");
     }
 
     #[test]
@@ -554,4 +1023,168 @@ mod arena_tests {
         assert_eq!(0, *(*f1).borrow(), "drop was called");
         assert_eq!(0, *(*f2).borrow(), "drop was called");
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn deferred_closures_run_in_drop_chain_order() {
+        let log = DropFlag::new(RefCell::new(Vec::new()));
+
+        let mem = Memory::new();
+        {
+            let arena = Arena::new(&mem).unwrap();
+            let l = log.clone();
+            arena.defer(move || l.borrow_mut().push("first")).unwrap();
+            let _obj = N::new(&arena, Nested { dropflag: DropFlag::new(RefCell::new(1)), inner: () }).unwrap();
+            let l = log.clone();
+            arena.defer(move || l.borrow_mut().push("second")).unwrap();
+
+            assert!(log.borrow().is_empty(), "deferred closures did not run");
+        }
+
+        assert_eq!(vec!["first", "second"], *log.borrow());
+    }
+
+    #[test]
+    fn on_drop_hooks_run_after_values_are_dropped() {
+        let flag = DropFlag::new(RefCell::new(1));
+        let seen = DropFlag::new(RefCell::new(Vec::new()));
+
+        let mem = Memory::new();
+        {
+            let arena = Arena::new(&mem).unwrap();
+            let (f, s) = (flag.clone(), seen.clone());
+            arena.on_drop(move || s.borrow_mut().push(("hook 1", *f.borrow()))).unwrap();
+            let (f, s) = (flag.clone(), seen.clone());
+            arena.on_drop(move || s.borrow_mut().push(("hook 2", *f.borrow()))).unwrap();
+            let _obj = N::new(&arena, Compact { value: flag.clone() }).unwrap();
+        }
+
+        assert_eq!(vec![("hook 1", 0), ("hook 2", 0)], *seen.borrow());
+    }
+
+    struct Logged {
+        name: &'static str,
+        log: DropFlag<Vec<&'static str>>,
+    }
+
+    impl Drop for Logged {
+        fn drop(&mut self) {
+            self.log.borrow_mut().push(self.name);
+        }
+    }
+
+    #[test]
+    fn drop_phases_are_executed_in_ascending_order() {
+        let log = DropFlag::new(RefCell::new(Vec::new()));
+        let sockets = DropPhase::new(0).unwrap();
+        let buffers = DropPhase::new(1).unwrap();
+        let loggers = DropPhase::new(2).unwrap();
+
+        let mem = Memory::new();
+        {
+            let arena = Arena::new(&mem).unwrap();
+            let _a = N::new_in_phase(&arena, loggers, Logged { name: "logger", log: log.clone() }).unwrap();
+            let _b = N::new_in_phase(&arena, buffers, Logged { name: "buffer", log: log.clone() }).unwrap();
+            let _c = N::new_in_phase(&arena, sockets, Logged { name: "socket", log: log.clone() }).unwrap();
+            let _d = N::new(&arena, Logged { name: "default", log: log.clone() }).unwrap();
+        }
+
+        assert_eq!(vec!["socket", "default", "buffer", "logger"], *log.borrow());
+    }
+
+    #[test]
+    fn drop_phases_are_executed_in_configured_order() {
+        let log = DropFlag::new(RefCell::new(Vec::new()));
+        let first = DropPhase::new(5).unwrap();
+        let second = DropPhase::new(3).unwrap();
+
+        let mem = Memory::new();
+        {
+            let arena = Arena::new(&mem).unwrap();
+            arena.set_drop_phase_order(&[first, second]);
+            let _a = N::new(&arena, Logged { name: "default", log: log.clone() }).unwrap();
+            let _b = N::new_in_phase(&arena, second, Logged { name: "second", log: log.clone() }).unwrap();
+            let _c = N::new_in_phase(&arena, first, Logged { name: "first", log: log.clone() }).unwrap();
+        }
+
+        assert_eq!(vec!["first", "second", "default"], *log.borrow());
+        assert_eq!(None, DropPhase::new(crate::MAX_DROP_PHASES as u8));
+    }
+
+    #[test]
+    fn background_drop_executes_drop_functions_on_another_thread() {
+        use std::sync::{Arc, Mutex};
+
+        struct SendLogged {
+            log: Arc<Mutex<Vec<std::thread::ThreadId>>>,
+        }
+
+        impl Drop for SendLogged {
+            fn drop(&mut self) {
+                self.log.lock().unwrap().push(std::thread::current().id());
+            }
+        }
+
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let mem = Memory::new();
+        {
+            let arena = Arena::new(&mem).unwrap();
+            unsafe { arena.set_background_drop(true) };
+            let list = crate::List::from_iter(&arena, (0..2000).map(|_| SendLogged { log: log.clone() })).unwrap();
+            drop(list);
+        }
+        mem.wait_for_teardowns();
+
+        assert_eq!(0, mem.pending_teardowns());
+        let log = log.lock().unwrap();
+        assert_eq!(2000, log.len());
+        assert!(log.iter().all(|id| *id != std::thread::current().id()), "dropped on background thread");
+    }
+
+    #[test]
+    fn tail_of_reused_region_is_kept_for_reuse() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        unsafe {
+            let region = arena.alloc_no_drop_items_aligned_uninit::<u64>(32, 8).unwrap();
+            arena.abandon_no_drop_items(region, 32, 8);
+            assert_eq!(256, arena.retention().reusable_bytes);
+
+            let head = arena.alloc_no_drop_items_aligned_uninit::<u64>(25, 8).unwrap();
+            assert_eq!(region, head);
+            assert_eq!(56, arena.retention().reusable_bytes);
+
+            let tail = arena.alloc_no_drop_items_aligned_uninit::<u64>(4, 8).unwrap();
+            assert_eq!(region.add(25), tail);
+            assert_eq!(24, arena.retention().reusable_bytes);
+        }
+    }
+
+    #[test]
+    fn panicking_background_drop_does_not_block_waiting() {
+        struct PanicOnDrop;
+
+        impl Drop for PanicOnDrop {
+            fn drop(&mut self) {
+                panic!("drop failed");
+            }
+        }
+
+        let mem = Memory::new();
+        {
+            let arena = Arena::new(&mem).unwrap();
+            unsafe { arena.set_background_drop(true) };
+            N::new(&arena, PanicOnDrop).unwrap();
+        }
+        mem.wait_for_teardowns();
+        assert_eq!(0, mem.pending_teardowns());
+
+        {
+            let arena = Arena::new(&mem).unwrap();
+            unsafe { arena.set_background_drop(true) };
+            N::new(&arena, 1).unwrap();
+        }
+        mem.wait_for_teardowns();
+        assert_eq!(0, mem.pending_teardowns());
+    }
+}
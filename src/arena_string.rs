@@ -0,0 +1,271 @@
+use crate::{Arena, WeakArena, UploadError, UStr, UStrError};
+use crate::ustr::MAX_USTR;
+use std::fmt::{Debug, Display, Write};
+use std::ptr::null_mut;
+
+/// Growable UTF-8 string stored in arena memory.
+///
+/// If the string data is the last allocation in arena, it grows in place at the end of the
+/// current block, otherwise it is relocated to a new place in arena, and the old bytes are
+/// kept for reuse by the following allocations of similar size.
+///
+/// Use `into_ustr` to finish the string and get the `UStr` without copying it.
+///
+/// ```rust
+/// use memur::{Memory, Arena, ArenaString};
+/// use std::fmt::Write;
+///
+/// let mem = Memory::new();
+/// let arena = Arena::new(&mem).unwrap();
+/// let mut s = ArenaString::new(&arena);
+/// write!(s, "layer {}", 3).unwrap();
+/// s.push_str("!").unwrap();
+/// assert_eq!("layer 3!", &s.into_ustr().unwrap());
+/// ```
+pub struct ArenaString {
+    _arena: WeakArena,
+    first: *mut u8,
+    len: usize,
+    capacity: usize,
+}
+
+impl ArenaString {
+    /// Creates an empty string. The memory is not allocated until the first write.
+    #[track_caller]
+    pub fn new(arena: &Arena) -> ArenaString {
+        ArenaString {
+            _arena: arena.to_weak_arena_for::<ArenaString>(),
+            first: null_mut(),
+            len: 0,
+            capacity: 0,
+        }
+    }
+
+    /// Creates an empty string with space for `capacity` bytes.
+    #[track_caller]
+    pub fn with_capacity(arena: &Arena, capacity: usize) -> Result<ArenaString, UploadError> {
+        let mut s = ArenaString::new(arena);
+        s.reserve(capacity)?;
+        Ok(s)
+    }
+
+    /// Creates a string from formatted arguments, as returned by `format_args!`.
+    #[track_caller]
+    pub fn from_fmt(arena: &Arena, args: std::fmt::Arguments<'_>) -> Result<ArenaString, UploadError> {
+        struct Writer<'a> {
+            s: &'a mut ArenaString,
+            error: Option<UploadError>,
+        }
+
+        impl<'a> Write for Writer<'a> {
+            fn write_str(&mut self, value: &str) -> std::fmt::Result {
+                self.s.push_str(value).map_err(|e| {
+                    self.error = Some(e);
+                    std::fmt::Error
+                })
+            }
+        }
+
+        let mut s = ArenaString::new(arena);
+        let mut writer = Writer { s: &mut s, error: None };
+        if writer.write_fmt(args).is_err() {
+            return Err(writer.error.expect("formatting fails only on upload error"));
+        }
+        Ok(s)
+    }
+
+    /// Returns the length of string in bytes.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns true if the string is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of bytes the string can hold without growing.
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Makes sure the string can hold `additional` more bytes, growing it in place if possible.
+    pub fn reserve(&mut self, additional: usize) -> Result<(), UploadError> {
+        let required = self.len.checked_add(additional).ok_or(UploadError::ItemDoesNotFit)?;
+        if required <= self.capacity {
+            return Ok(());
+        }
+        let arena = self._arena.arena().ok_or(UploadError::ArenaIsNotAlive)?;
+        let new_capacity = required.max(self.capacity.saturating_mul(2)).max(8);
+        unsafe {
+            if !self.first.is_null() && arena.try_grow_in_place(self.first, self.capacity, new_capacity) {
+                self.capacity = new_capacity;
+                return Ok(());
+            }
+            let first = arena.alloc_no_drop_items_aligned_uninit::<u8>(new_capacity, 1)?;
+            if !self.first.is_null() {
+                std::ptr::copy_nonoverlapping(self.first, first, self.len);
+                arena.abandon_no_drop_items(self.first, self.capacity, 1);
+            }
+            self.first = first;
+            self.capacity = new_capacity;
+        }
+        Ok(())
+    }
+
+    /// Appends the string slice.
+    pub fn push_str(&mut self, value: &str) -> Result<(), UploadError> {
+        self.reserve(value.len())?;
+        unsafe { std::ptr::copy_nonoverlapping(value.as_ptr(), self.first.add(self.len), value.len()) };
+        self.len += value.len();
+        Ok(())
+    }
+
+    /// Appends the char.
+    pub fn push(&mut self, value: char) -> Result<(), UploadError> {
+        self.push_str(value.encode_utf8(&mut [0; 4]))
+    }
+
+    /// Shortens the string to `new_len` bytes. Does nothing if the string is already shorter.
+    ///
+    /// Panics if `new_len` is not on a char boundary.
+    pub fn truncate(&mut self, new_len: usize) {
+        if new_len < self.len {
+            assert!(self.as_str().is_char_boundary(new_len), "new_len is not on a char boundary");
+            self.len = new_len;
+        }
+    }
+
+    /// Returns the string slice.
+    pub fn as_str(&self) -> &str {
+        if self.first.is_null() {
+            return "";
+        }
+        // memory is returned only when the weak reference is dropped, so this is ok
+        unsafe { std::str::from_utf8_unchecked(std::slice::from_raw_parts(self.first, self.len)) }
+    }
+
+    /// Appends the nul terminator and converts the string to `UStr` without copying it.
+    ///
+    /// Returns an error if the string contains nul or is too long for `UStr`.
+    pub fn into_ustr(mut self) -> Result<UStr, UStrError> {
+        if self.as_str().bytes().any(|byte| byte == b'\0') {
+            return Err(UStrError::StrContainsNul);
+        }
+        if self.len > MAX_USTR {
+            return Err(UStrError::StringIsTooLong { length: self.len, max_size: MAX_USTR });
+        }
+        self.reserve(1)?;
+        unsafe { *self.first.add(self.len) = 0 };
+        Ok(UStr {
+            _arena: self._arena,
+            byte_count_without_nul: self.len as u32,
+            first: self.first,
+        })
+    }
+}
+
+impl Write for ArenaString {
+    fn write_str(&mut self, value: &str) -> std::fmt::Result {
+        self.push_str(value).map_err(|_| std::fmt::Error)
+    }
+}
+
+impl AsRef<str> for ArenaString {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl Debug for ArenaString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl Display for ArenaString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self.as_str(), f)
+    }
+}
+
+/// Formats the arguments directly into arena and returns `Result<UStr, UStrError>`.
+///
+/// ```rust
+/// use memur::{Memory, Arena, ustr};
+///
+/// let mem = Memory::new();
+/// let arena = Arena::new(&mem).unwrap();
+/// let name = ustr!(&arena, "texture_{}", 42).unwrap();
+/// assert_eq!("texture_42", &name);
+/// ```
+#[macro_export]
+macro_rules! ustr {
+    ($arena:expr, $($arg:tt)*) => {
+        $crate::ArenaString::from_fmt($arena, format_args!($($arg)*))
+            .map_err($crate::UStrError::from)
+            .and_then(|s| s.into_ustr())
+    };
+}
+
+#[cfg(test)]
+mod arena_string_tests {
+    use crate::{Memory, Arena, ArenaString, UStr, UStrError, UploadError, N};
+    use std::fmt::Write;
+
+    #[test]
+    fn grows_in_place_when_last_allocation() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut s = ArenaString::with_capacity(&arena, 8).unwrap();
+        s.push_str("12345678").unwrap();
+        let first = s.as_str().as_ptr();
+        s.push_str("9").unwrap();
+        assert_eq!(first, s.as_str().as_ptr(), "grown in place");
+
+        let _other = N::new(&arena, 1u64).unwrap();
+        s.push_str(&"0".repeat(100)).unwrap();
+        assert_ne!(first, s.as_str().as_ptr(), "relocated after another allocation");
+        assert_eq!(format!("123456789{}", "0".repeat(100)), s.as_str());
+    }
+
+    #[test]
+    fn fails_when_capacity_overflows() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut s = ArenaString::new(&arena);
+        s.push_str("hello").unwrap();
+        assert!(matches!(s.reserve(usize::MAX), Err(UploadError::ItemDoesNotFit)));
+        s.push_str(" world").unwrap();
+        assert_eq!("hello world", s.as_str());
+    }
+
+    #[test]
+    fn formats_into_ustr() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut s = ArenaString::new(&arena);
+        let (name, index) = ("a", 1);
+        write!(s, "{}-{}", name, index).unwrap();
+        s.push('é').unwrap();
+        s.truncate(3);
+        assert_eq!("a-1", s.as_str());
+
+        let ustr = s.into_ustr().unwrap();
+        assert_eq!(UStr::from_str(&arena, "a-1").unwrap(), ustr);
+        assert_eq!("a-1", &ustr!(&arena, "{}-{}", "a", 1).unwrap());
+        let nul = '\0';
+        assert!(matches!(ustr!(&arena, "a{}b", nul), Err(UStrError::StrContainsNul)));
+    }
+
+    #[test]
+    fn fails_when_arena_is_dead() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut s = ArenaString::new(&arena);
+        s.push_str("value").unwrap();
+        drop(arena);
+        assert!(s.push_str("more").is_err());
+        assert_eq!("value", s.as_str());
+    }
+}
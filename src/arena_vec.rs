@@ -0,0 +1,393 @@
+use crate::{Arena, UploadError, WeakArena, Guard, GuardMut, FixedArray};
+use crate::array_uninit::{ArrayMetadata, drop_array};
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+/// Growable array that stores items contiguously in arena memory.
+///
+/// Unlike `Array`, the items are stored inline, so the vector can be used as `&[T]`.
+/// When the capacity is exceeded, the items grow in place if they are the last allocation in the
+/// current block, otherwise they are moved to a larger region of arena, and the old region is
+/// kept for reuse by the following allocations of similar size.
+///
+/// The items are dropped together with arena, or can be converted to `FixedArray` without copying.
+///
+/// ```rust
+/// use memur::{Memory, Arena, ArenaVec};
+///
+/// let mem = Memory::new();
+/// let arena = Arena::new(&mem).unwrap();
+/// let mut items = ArenaVec::new(&arena).unwrap();
+/// items.push(3).unwrap();
+/// items.push(1).unwrap();
+/// items.push(2).unwrap();
+/// items.sort();
+/// assert_eq!(&[1, 2, 3], &items[..]);
+///
+/// let fixed = items.into_fixed_array();
+/// assert_eq!(&[1, 2, 3], fixed.as_ref());
+/// ```
+pub struct ArenaVec<T> where T: Sized {
+    _arena: WeakArena,
+    _capacity: usize,
+    _metadata: *mut ArrayMetadata<T>,
+}
+
+impl<T> ArenaVec<T> where T: Sized {
+    /// Creates an empty vector. The memory for items is not allocated until the first push.
+    #[track_caller]
+    pub fn new(arena: &Arena) -> Result<ArenaVec<T>, UploadError> {
+        unsafe {
+            let metadata = arena.upload_no_drop::<ArrayMetadata<T>>(ArrayMetadata::<T> {
+                _len: 0,
+                _data: NonNull::dangling().as_ptr(),
+            })?;
+
+            arena.push_custom_drop_fn(drop_array::<T>, metadata as *const u8)?;
+
+            Ok(ArenaVec {
+                _arena: arena.to_weak_arena_for::<Self>(),
+                _capacity: if std::mem::size_of::<T>() == 0 { usize::MAX } else { 0 },
+                _metadata: metadata,
+            })
+        }
+    }
+
+    /// Creates an empty vector with space for `capacity` items.
+    #[track_caller]
+    pub fn with_capacity(arena: &Arena, capacity: usize) -> Result<ArenaVec<T>, UploadError> {
+        let mut items = ArenaVec::new(arena)?;
+        items.reserve(capacity)?;
+        Ok(items)
+    }
+
+    /// Creates a vector by consuming an iterator.
+    #[track_caller]
+    pub fn from_iter<I: IntoIterator<Item = T>>(arena: &Arena, iter: I) -> Result<ArenaVec<T>, UploadError> {
+        let mut items = ArenaVec::new(arena)?;
+        items.extend(iter)?;
+        Ok(items)
+    }
+
+    /// Returns the number of items if the arena is alive.
+    pub fn len(&self) -> Option<usize> {
+        if self._arena.is_alive() {
+            Some(unsafe { (*self._metadata)._len })
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if arena is dead or vector is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len().unwrap_or(0) == 0
+    }
+
+    /// Returns the number of items the vector can hold without moving.
+    pub fn capacity(&self) -> usize {
+        self._capacity
+    }
+
+    /// Returns guarded items if the arena is alive. The guard keeps the arena alive until it is dropped.
+    pub fn get(&self) -> Option<Guard<'_, [T]>> {
+        let arena = self._arena.arena()?;
+        Some(Guard::new(arena, self.as_slice()))
+    }
+
+    /// Returns guarded mutable items if the arena is alive. The guard keeps the arena alive until it is dropped.
+    pub fn get_mut(&mut self) -> Option<GuardMut<'_, [T]>> {
+        let arena = self._arena.arena()?;
+        Some(GuardMut::new(arena, self.as_mut_slice()))
+    }
+
+    /// Makes sure the vector can hold `additional` more items, growing it in place if possible.
+    pub fn reserve(&mut self, additional: usize) -> Result<(), UploadError> {
+        let arena = self._arena.arena().ok_or(UploadError::ArenaIsNotAlive)?;
+        unsafe {
+            let meta = &mut *self._metadata;
+            let required = meta._len.checked_add(additional).ok_or(UploadError::ItemDoesNotFit)?;
+            if required <= self._capacity {
+                return Ok(());
+            }
+            let new_capacity = required.max(self._capacity.saturating_mul(2)).max(4);
+            let item_size = std::mem::size_of::<T>();
+            let new_bytes = new_capacity.checked_mul(item_size).ok_or(UploadError::ItemDoesNotFit)?;
+            // the current capacity was already allocated, so its size in bytes does not overflow
+            if self._capacity > 0 && arena.try_grow_in_place(meta._data as *const u8, self._capacity * item_size, new_bytes) {
+                self._capacity = new_capacity;
+                return Ok(());
+            }
+            let data = arena.alloc_no_drop_items_aligned_uninit::<T>(new_capacity, item_size)?;
+            std::ptr::copy_nonoverlapping(meta._data, data, meta._len);
+            if self._capacity > 0 {
+                arena.abandon_no_drop_items(meta._data, self._capacity, item_size);
+            }
+            meta._data = data;
+            self._capacity = new_capacity;
+        }
+        Ok(())
+    }
+
+    /// Appends the item to the end of the vector.
+    ///
+    /// Returns an error if the arena is dead or the item can not be placed in arena.
+    pub fn push(&mut self, item: T) -> Result<(), UploadError> {
+        self.reserve(1)?;
+        unsafe {
+            let meta = &mut *self._metadata;
+            std::ptr::write(meta._data.add(meta._len), item);
+            meta._len += 1;
+        }
+        Ok(())
+    }
+
+    /// Appends all items of the iterator.
+    pub fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) -> Result<(), UploadError> {
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0)?;
+        for item in iter {
+            self.push(item)?;
+        }
+        Ok(())
+    }
+
+    /// Removes and returns the last item.
+    pub fn pop(&mut self) -> Option<T> {
+        if !self._arena.is_alive() {
+            return None;
+        }
+        unsafe {
+            let meta = &mut *self._metadata;
+            if meta._len == 0 {
+                return None;
+            }
+            meta._len -= 1;
+            Some(std::ptr::read(meta._data.add(meta._len)))
+        }
+    }
+
+    /// Inserts the item at `index`, shifting all items after it to the right.
+    ///
+    /// Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, item: T) -> Result<(), UploadError> {
+        let len = self.len().ok_or(UploadError::ArenaIsNotAlive)?;
+        assert!(index <= len, "insertion index (is {}) should be <= len (is {})", index, len);
+        self.reserve(1)?;
+        unsafe {
+            let meta = &mut *self._metadata;
+            let ptr = meta._data.add(index);
+            std::ptr::copy(ptr, ptr.add(1), len - index);
+            std::ptr::write(ptr, item);
+            meta._len += 1;
+        }
+        Ok(())
+    }
+
+    /// Removes and returns the item at `index`, shifting all items after it to the left.
+    ///
+    /// Returns `None` if the arena is dead. Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        let len = self.len()?;
+        assert!(index < len, "removal index (is {}) should be < len (is {})", index, len);
+        unsafe {
+            let meta = &mut *self._metadata;
+            let ptr = meta._data.add(index);
+            let item = std::ptr::read(ptr);
+            std::ptr::copy(ptr.add(1), ptr, len - index - 1);
+            meta._len -= 1;
+            Some(item)
+        }
+    }
+
+    /// Drops the items after `len`. Does nothing if the vector is already shorter.
+    pub fn truncate(&mut self, len: usize) {
+        if !self._arena.is_alive() {
+            return;
+        }
+        unsafe {
+            let meta = &mut *self._metadata;
+            if len >= meta._len {
+                return;
+            }
+            let tail = std::ptr::slice_from_raw_parts_mut(meta._data.add(len), meta._len - len);
+            // the length is updated first, so that a panic in drop does not drop the items twice
+            meta._len = len;
+            std::ptr::drop_in_place(tail);
+        }
+    }
+
+    /// Drops all items.
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
+    /// Returns the items, or an empty slice if the arena is dead.
+    pub fn as_slice(&self) -> &[T] {
+        if !self._arena.is_alive() {
+            return &[];
+        }
+        unsafe { std::slice::from_raw_parts((*self._metadata)._data, (*self._metadata)._len) }
+    }
+
+    /// Returns the mutable items, or an empty slice if the arena is dead.
+    pub fn as_mut_slice(&mut self) -> &mut [T] {
+        if !self._arena.is_alive() {
+            return &mut [];
+        }
+        unsafe { std::slice::from_raw_parts_mut((*self._metadata)._data, (*self._metadata)._len) }
+    }
+
+    /// Converts to `FixedArray` that points to the same items, without copying them.
+    ///
+    /// The unused capacity is kept for reuse by the following allocations of similar size.
+    pub fn into_fixed_array(self) -> FixedArray<T> {
+        if let Some(arena) = self._arena.arena() {
+            unsafe {
+                let meta = &*self._metadata;
+                if self._capacity > meta._len {
+                    arena.abandon_no_drop_items(meta._data.add(meta._len), self._capacity - meta._len, std::mem::size_of::<T>());
+                }
+            }
+        }
+        FixedArray {
+            _arena: self._arena,
+            _metadata: self._metadata,
+        }
+    }
+}
+
+impl<T> Deref for ArenaVec<T> {
+    type Target = [T];
+
+    #[inline(always)]
+    fn deref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T> DerefMut for ArenaVec<T> {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T> AsRef<[T]> for ArenaVec<T> {
+    #[inline(always)]
+    fn as_ref(&self) -> &[T] {
+        self.as_slice()
+    }
+}
+
+impl<T> AsMut<[T]> for ArenaVec<T> {
+    #[inline(always)]
+    fn as_mut(&mut self) -> &mut [T] {
+        self.as_mut_slice()
+    }
+}
+
+impl<T> std::fmt::Debug for ArenaVec<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.as_slice()).finish()
+    }
+}
+
+impl<T> PartialEq for ArenaVec<T> where T: PartialEq {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_slice() == other.as_slice()
+    }
+}
+
+#[cfg(test)]
+mod arena_vec_tests {
+    use crate::{Memory, Arena, ArenaVec, N, UploadError};
+    use crate::dropflag::{Droppable, DropFlag};
+    use std::cell::RefCell;
+
+    #[test]
+    fn grows_in_place_when_last_allocation() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut items = ArenaVec::with_capacity(&arena, 4).unwrap();
+        items.extend(0..4u64).unwrap();
+        let first = items.as_ptr();
+        items.push(4).unwrap();
+        assert_eq!(first, items.as_ptr(), "grown in place");
+
+        let _other = N::new(&arena, 1u8).unwrap();
+        items.extend(5..100).unwrap();
+        assert_ne!(first, items.as_ptr(), "moved after another allocation");
+        assert_eq!((0..100).collect::<Vec<u64>>(), items.to_vec());
+    }
+
+    #[test]
+    fn edits_like_vec() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut items = ArenaVec::from_iter(&arena, vec![3, 1, 2]).unwrap();
+        items.sort();
+        items.insert(0, 0).unwrap();
+        assert_eq!(Some(2), items.remove(2));
+        assert_eq!(Some(3), items.pop());
+        items[1] = 10;
+        assert_eq!(&[0, 10], &items[..]);
+        assert_eq!(Some(2), items.len());
+
+        let zero_sized = ArenaVec::from_iter(&arena, vec![(), ()]).unwrap();
+        assert_eq!(2, zero_sized.iter().count());
+    }
+
+    #[test]
+    fn drops_truncated_items_and_items_of_fixed_array() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let flags: Vec<_> = (0..3).map(|_| DropFlag::new(RefCell::new(false))).collect();
+        let mut items = ArenaVec::new(&arena).unwrap();
+        for flag in &flags {
+            items.push(Droppable { dropflag: flag.clone() }).unwrap();
+        }
+        items.truncate(2);
+        assert!(*flags[2].borrow());
+        assert!(!*flags[1].borrow());
+
+        let fixed = items.into_fixed_array();
+        assert_eq!(Some(2), fixed.len());
+        drop(arena);
+        assert!(flags.iter().all(|flag| *flag.borrow()));
+        assert_eq!(None, fixed.len());
+    }
+
+    #[test]
+    fn unused_capacity_of_fixed_array_is_reused() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut items = ArenaVec::with_capacity(&arena, 64).unwrap();
+        items.extend(0..2u64).unwrap();
+        let fixed = items.into_fixed_array();
+        assert_eq!(62 * 8, arena.retention().reusable_bytes);
+        assert_eq!(&[0, 1], &*fixed.get().unwrap());
+    }
+
+    #[test]
+    fn fails_when_arena_is_dead() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut items = ArenaVec::from_iter(&arena, 0..3).unwrap();
+        drop(arena);
+        assert!(items.is_empty());
+        assert!(matches!(items.push(3), Err(UploadError::ArenaIsNotAlive)));
+        assert!(items.pop().is_none());
+    }
+
+    #[test]
+    fn fails_when_capacity_overflows() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut items = ArenaVec::from_iter(&arena, 0..3u64).unwrap();
+        assert!(matches!(items.reserve(usize::MAX), Err(UploadError::ItemDoesNotFit)));
+        assert!(matches!(items.reserve(usize::MAX / 8), Err(UploadError::ItemDoesNotFit)));
+        assert!(matches!(unsafe { arena.alloc_no_drop_items_aligned_uninit::<u64>(usize::MAX / 4, 8) }, Err(UploadError::ItemDoesNotFit)));
+        items.push(3).unwrap();
+        assert_eq!(vec![0, 1, 2, 3], items.to_vec());
+    }
+}
@@ -1,16 +1,37 @@
-use crate::{Arena, UploadError, WeakArena};
+use crate::{Arena, UploadError, WeakArena, Guard, GuardMut, FixedArray};
+use crate::array_uninit::{ArrayMetadata, drop_array};
 use std::ptr::null_mut;
-use std::ops::{Index, IndexMut};
+use std::ops::{Index, IndexMut, RangeBounds, Bound};
+use std::cmp::Ordering;
 
 /// Arena‐uploaded metadata for the growable array. It stores:
 /// - `_len`: the number of pushed items,
 /// - `_capacity`: the size of the pointer table,
-/// - `_ptrs`: a pointer to the table of pointers (each pointer refers to an item of type `T`).
+/// - `_ptrs`: a pointer to the table of pointers (each pointer refers to an item of type `T`),
+/// - `_free`: the number of removed item slots, kept in the pointer table after the `_len` items
+///   so that they can be reused by the following pushes.
 #[repr(C)]
 pub struct GrowableArrayMetadata<T> {
     pub _len: usize,
     pub _capacity: usize,
     pub _ptrs: *mut *mut T,
+    pub _free: usize,
+}
+
+/// Arena memory of a single array item. The pointer table points to the `value`, and the
+/// `generation` is reset to 0 when the item is removed from the array.
+#[repr(C)]
+pub(crate) struct ArraySlot<T> {
+    pub(crate) generation: u64,
+    pub(crate) value: T,
+}
+
+impl<T> ArraySlot<T> {
+    /// Returns the slot of the item pointed to by the pointer table.
+    #[inline(always)]
+    pub(crate) unsafe fn from_value_ptr(item_ptr: *mut T) -> *mut ArraySlot<T> {
+        (item_ptr as *mut u8).sub(std::mem::offset_of!(ArraySlot<T>, value)) as *mut ArraySlot<T>
+    }
 }
 
 /// Custom drop function for a growable array. When the arena dies this function is invoked
@@ -43,11 +64,13 @@ where
 
 impl<T> Array<T> {
     /// Creates a new array with a default initial capacity.
+    #[track_caller]
     pub fn new(arena: &Arena) -> Result<Self, UploadError> {
         Self::with_capacity(arena, 4)
     }
 
     /// Creates a new array with the specified capacity for the pointer table.
+    #[track_caller]
     pub fn with_capacity(arena: &Arena, capacity: usize) -> Result<Self, UploadError> {
         unsafe {
             // Upload the metadata (with zero items initially).
@@ -55,6 +78,7 @@ impl<T> Array<T> {
                 _len: 0,
                 _capacity: capacity,
                 _ptrs: null_mut(),
+                _free: 0,
             })?;
             // Register our custom drop function so that items get dropped when the arena dies.
             arena.push_custom_drop_fn(drop_growable_array::<T>, metadata as *const u8)?;
@@ -67,7 +91,44 @@ impl<T> Array<T> {
             (*metadata)._ptrs = ptrs;
 
             Ok(Array {
-                _arena: arena.to_weak_arena(),
+                _arena: arena.to_weak_arena_for::<Self>(),
+                _metadata: metadata,
+            })
+        }
+    }
+
+    /// Moves the items to a new contiguous `FixedArray` in the same arena.
+    ///
+    /// The items are dropped only by the returned array, and the removed item slots and the pointer
+    /// table of this array are left behind in arena. If the arena is dead or the new array can not
+    /// be placed in arena, the items stay where they are and are dropped together with the arena.
+    #[track_caller]
+    pub fn into_fixed_array(self) -> Result<FixedArray<T>, UploadError> {
+        let arena = self._arena.arena().ok_or(UploadError::ArenaIsNotAlive)?;
+        unsafe {
+            let meta = &mut *self._metadata;
+            let len = meta._len;
+            let metadata = arena.upload_no_drop::<ArrayMetadata<T>>(ArrayMetadata::<T> {
+                _len: 0,
+                _data: null_mut(),
+            })?;
+            arena.push_custom_drop_fn(drop_array::<T>, metadata as *const u8)?;
+            let data = arena.alloc_no_drop_items_aligned_uninit::<T>(len, std::mem::size_of::<T>())?;
+
+            for i in 0..len {
+                std::ptr::write(data.add(i), Self::take_item(*meta._ptrs.add(i)));
+            }
+            // nothing is left to drop in this array, and the table is no longer used
+            arena.abandon_no_drop_items(meta._ptrs, meta._capacity, std::mem::size_of::<*mut T>());
+            meta._len = 0;
+            meta._free = 0;
+            meta._capacity = 0;
+            meta._ptrs = null_mut();
+
+            (*metadata)._data = data;
+            (*metadata)._len = len;
+            Ok(FixedArray {
+                _arena: arena.to_weak_arena_for::<FixedArray<T>>(),
                 _metadata: metadata,
             })
         }
@@ -84,6 +145,7 @@ impl<T> Array<T> {
     /// Creates a new array by consuming an iterator.
     ///
     /// The capacity is chosen based on the iterator’s size hint (at least 4).
+    #[track_caller]
     pub fn from_iter<I: IntoIterator<Item = T>>(arena: &Arena, iter: I) -> Result<Self, UploadError> {
         let iter = iter.into_iter();
         let (lower, _) = iter.size_hint();
@@ -113,6 +175,20 @@ impl<T> Array<T> {
         }
     }
 
+    /// Returns a guarded reference to this array if the arena is alive. The guard keeps the arena
+    /// alive until it is dropped, so the items can be safely accessed over it.
+    pub fn get(&self) -> Option<Guard<'_, Array<T>>> {
+        let arena = self._arena.arena()?;
+        Some(Guard::new(arena, self))
+    }
+
+    /// Returns a guarded mutable reference to this array if the arena is alive. The guard keeps the arena
+    /// alive until it is dropped, so the items can be safely accessed over it.
+    pub fn get_mut(&mut self) -> Option<GuardMut<'_, Array<T>>> {
+        let arena = self._arena.arena()?;
+        Some(GuardMut::new(arena, self))
+    }
+
     /// Returns true if the array is empty (or if the arena is dead).
     pub fn is_empty(&self) -> bool {
         self.len().unwrap_or(0) == 0
@@ -122,13 +198,31 @@ impl<T> Array<T> {
     ///
     /// The item is allocated in the arena and its pointer is stored. If there is no room in the pointer
     /// table, a new (larger) table is allocated and the existing pointers are copied over.
+    ///
+    /// Returns `UploadError::ArenaIsNotAlive` if the arena is dead, like the other editing methods.
     pub fn push(&mut self, item: T) -> Result<(), UploadError> {
-        if !self._arena.is_alive() {
-            panic!("Arena is dead");
-        }
-        let arena = self._arena.arena().expect("Arena is dead");
+        let arena = self._arena.arena().ok_or(UploadError::ArenaIsNotAlive)?;
         unsafe {
+            let item_ptr = self.alloc_item(&arena, item)?;
             let meta = &mut *self._metadata;
+            // Store the pointer in the pointer table.
+            *meta._ptrs.add(meta._len) = item_ptr;
+            meta._len += 1;
+        }
+        Ok(())
+    }
+
+    /// Makes room for one more pointer in the pointer table and places the item in arena.
+    ///
+    /// The item is placed in the slot of a removed item if there is one, otherwise in a new slot.
+    unsafe fn alloc_item(&mut self, arena: &Arena, item: T) -> Result<*mut T, UploadError> {
+        let meta = &mut *self._metadata;
+        let slot_ptr = if meta._free > 0 {
+            // The slot is at the position of the new item, and the generation stamp is renewed,
+            // so that weak handles to the removed item stay dead.
+            meta._free -= 1;
+            ArraySlot::from_value_ptr(*meta._ptrs.add(meta._len))
+        } else {
             if meta._len == meta._capacity {
                 // Grow: double the capacity (or use 4 if capacity is 0).
                 let new_capacity = if meta._capacity == 0 { 4 } else { meta._capacity * 2 };
@@ -137,22 +231,286 @@ impl<T> Array<T> {
                     std::mem::size_of::<*mut T>(),
                 )? as *mut *mut T;
                 std::ptr::copy_nonoverlapping(meta._ptrs, new_ptrs, meta._len);
+                // The old table is no longer used, let the following tables reuse it.
+                arena.abandon_no_drop_items(meta._ptrs, meta._capacity, std::mem::size_of::<*mut T>());
                 meta._ptrs = new_ptrs;
                 meta._capacity = new_capacity;
             }
-            // Allocate space for the new item.
-            let item_ptr = arena.alloc_no_drop_items_aligned_uninit::<T>(
+            // Allocate space for the new item together with its generation stamp.
+            arena.alloc_no_drop_items_aligned_uninit::<ArraySlot<T>>(
                 1,
-                std::mem::size_of::<T>(),
-            )? as *mut T;
-            std::ptr::write(item_ptr, item);
-            // Store the pointer in the pointer table.
-            *meta._ptrs.add(meta._len) = item_ptr;
+                std::mem::size_of::<ArraySlot<T>>(),
+            )?
+        };
+        std::ptr::write(slot_ptr, ArraySlot {
+            generation: arena.next_generation(),
+            value: item,
+        });
+        Ok(std::ptr::addr_of_mut!((*slot_ptr).value))
+    }
+
+    /// Marks the item as removed from the array and moves it out.
+    #[inline(always)]
+    unsafe fn take_item(item_ptr: *mut T) -> T {
+        (*ArraySlot::from_value_ptr(item_ptr)).generation = 0;
+        std::ptr::read(item_ptr)
+    }
+
+    /// Marks the item as removed from the array and drops it.
+    #[inline(always)]
+    unsafe fn drop_item(item_ptr: *mut T) {
+        (*ArraySlot::from_value_ptr(item_ptr)).generation = 0;
+        std::ptr::drop_in_place(item_ptr);
+    }
+
+    /// Returns the pointer table, or an empty table if the arena is dead.
+    #[inline(always)]
+    fn ptrs_mut(&mut self) -> &mut [*mut T] {
+        match self.len() {
+            Some(len) if len > 0 => unsafe { std::slice::from_raw_parts_mut((*self._metadata)._ptrs, len) },
+            _ => &mut [],
+        }
+    }
+
+    /// Appends all items of the iterator.
+    pub fn extend<I: IntoIterator<Item = T>>(&mut self, iter: I) -> Result<(), UploadError> {
+        for item in iter {
+            self.push(item)?;
+        }
+        Ok(())
+    }
+
+    /// Inserts the item at `index`, shifting the pointers of all items after it to the right.
+    ///
+    /// The items are not moved in memory. Panics if `index > len`.
+    pub fn insert(&mut self, index: usize, item: T) -> Result<(), UploadError> {
+        let arena = self._arena.arena().ok_or(UploadError::ArenaIsNotAlive)?;
+        unsafe {
+            let len = (*self._metadata)._len;
+            assert!(index <= len, "insertion index (is {}) should be <= len (is {})", index, len);
+            let item_ptr = self.alloc_item(&arena, item)?;
+            let meta = &mut *self._metadata;
+            let ptr = meta._ptrs.add(index);
+            std::ptr::copy(ptr, ptr.add(1), len - index);
+            *ptr = item_ptr;
             meta._len += 1;
         }
         Ok(())
     }
 
+    /// Removes and returns the item at `index`, shifting the pointers of all items after it to the left.
+    ///
+    /// Returns `None` if the arena is dead. Panics if `index` is out of bounds.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        let len = self.len()?;
+        assert!(index < len, "removal index (is {}) should be < len (is {})", index, len);
+        unsafe {
+            let meta = &mut *self._metadata;
+            let ptr = meta._ptrs.add(index);
+            let item_ptr = *ptr;
+            let item = Self::take_item(item_ptr);
+            std::ptr::copy(ptr.add(1), ptr, len - index - 1);
+            // keep the removed slot for reuse
+            *meta._ptrs.add(len - 1) = item_ptr;
+            meta._len -= 1;
+            meta._free += 1;
+            Some(item)
+        }
+    }
+
+    /// Removes and returns the item at `index`, replacing it with the last item.
+    ///
+    /// Returns `None` if the arena is dead. Panics if `index` is out of bounds.
+    pub fn swap_remove(&mut self, index: usize) -> Option<T> {
+        let len = self.len()?;
+        assert!(index < len, "swap_remove index (is {}) should be < len (is {})", index, len);
+        unsafe {
+            let meta = &mut *self._metadata;
+            let item_ptr = *meta._ptrs.add(index);
+            let item = Self::take_item(item_ptr);
+            *meta._ptrs.add(index) = *meta._ptrs.add(len - 1);
+            // keep the removed slot for reuse
+            *meta._ptrs.add(len - 1) = item_ptr;
+            meta._len -= 1;
+            meta._free += 1;
+            Some(item)
+        }
+    }
+
+    /// Swaps the items at `a` and `b` by swapping their pointers.
+    ///
+    /// Panics if `a` or `b` are out of bounds.
+    pub fn swap(&mut self, a: usize, b: usize) {
+        self.ptrs_mut().swap(a, b)
+    }
+
+    /// Reverses the order of items by reversing the pointer table.
+    pub fn reverse(&mut self) {
+        self.ptrs_mut().reverse()
+    }
+
+    /// Sorts the items with the comparator function by sorting the pointer table.
+    ///
+    /// The sort is stable, and the items are not moved in memory.
+    pub fn sort_by<F>(&mut self, mut compare: F) where F: FnMut(&T, &T) -> Ordering {
+        self.ptrs_mut().sort_by(|a, b| unsafe { compare(&**a, &**b) })
+    }
+
+    /// Sorts the items with the key extraction function by sorting the pointer table.
+    ///
+    /// The sort is stable, and the items are not moved in memory.
+    pub fn sort_by_key<K, F>(&mut self, mut f: F) where F: FnMut(&T) -> K, K: Ord {
+        self.ptrs_mut().sort_by_key(|a| unsafe { f(&**a) })
+    }
+
+    /// Drops the items after `len`. Does nothing if the array is already shorter.
+    pub fn truncate(&mut self, len: usize) {
+        let old_len = match self.len() {
+            Some(old_len) if len < old_len => old_len,
+            _ => return,
+        };
+        unsafe {
+            // the length is updated first, so that a panic in drop does not drop the items twice
+            (*self._metadata)._len = len;
+            (*self._metadata)._free += old_len - len;
+            for i in len..old_len {
+                Self::drop_item(*(*self._metadata)._ptrs.add(i));
+            }
+        }
+    }
+
+    /// Drops all items.
+    pub fn clear(&mut self) {
+        self.truncate(0)
+    }
+
+    /// Retains only the items for which the predicate returns true, preserving their order.
+    pub fn retain<F>(&mut self, mut f: F) where F: FnMut(&T) -> bool {
+        let len = match self.len() {
+            Some(len) => len,
+            None => return,
+        };
+
+        /// Moves the pointers of deleted items after the retained items when the retain is finished,
+        /// or if the predicate or drop panics.
+        struct RetainGuard<T> {
+            meta: *mut GrowableArrayMetadata<T>,
+            original_len: usize,
+            processed: usize,
+            deleted: usize,
+        }
+
+        impl<T> Drop for RetainGuard<T> {
+            fn drop(&mut self) {
+                unsafe {
+                    let meta = &mut *self.meta;
+                    // the table has retained, deleted and unprocessed items, in this order
+                    let retained = self.processed - self.deleted;
+                    std::slice::from_raw_parts_mut(meta._ptrs.add(retained), self.original_len - retained)
+                        .rotate_left(self.deleted);
+                    meta._len = self.original_len - self.deleted;
+                    meta._free += self.deleted;
+                }
+            }
+        }
+
+        unsafe { (*self._metadata)._len = 0 };
+        let mut guard = RetainGuard { meta: self._metadata, original_len: len, processed: 0, deleted: 0 };
+        while guard.processed < len {
+            unsafe {
+                let ptrs = (*guard.meta)._ptrs;
+                let item_ptr = *ptrs.add(guard.processed);
+                if f(&*item_ptr) {
+                    std::ptr::swap(ptrs.add(guard.processed - guard.deleted), ptrs.add(guard.processed));
+                    guard.processed += 1;
+                } else {
+                    guard.processed += 1;
+                    guard.deleted += 1;
+                    Self::drop_item(item_ptr);
+                }
+            }
+        }
+    }
+
+    /// Removes the items in the range and returns them in an iterator.
+    ///
+    /// The items that are not consumed are dropped when the iterator is dropped.
+    /// Returns no items if the arena is dead. Panics if the range is out of bounds.
+    pub fn drain<R>(&mut self, range: R) -> ArrayDrain<'_, T> where R: RangeBounds<usize> {
+        let len = self.len().unwrap_or(0);
+        let start = match range.start_bound() {
+            Bound::Included(&start) => start,
+            Bound::Excluded(&start) => start + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&end) => end + 1,
+            Bound::Excluded(&end) => end,
+            Bound::Unbounded => len,
+        };
+        assert!(start <= end, "drain start (is {}) should be <= end (is {})", start, end);
+        assert!(end <= len, "drain end (is {}) should be <= len (is {})", end, len);
+        let mut free = 0;
+        if self._arena.is_alive() {
+            // the drained and tail items are restored when the iterator is dropped, so that
+            // forgetting the iterator only leaks them
+            unsafe {
+                free = (*self._metadata)._free;
+                (*self._metadata)._len = start;
+                (*self._metadata)._free = 0;
+            }
+        }
+        ArrayDrain {
+            array: self,
+            index: start,
+            end,
+            tail_len: len - end,
+            free,
+        }
+    }
+
+    /// Returns a reference to the item at `index` if the arena is alive and the index is in bounds.
+    ///
+    /// This is the `Vec::get` of this array: `get` already returns the guard of the whole array,
+    /// the same as `get` of the other arena handles.
+    pub fn get_at(&self, index: usize) -> Option<&T> {
+        if index >= self.len()? {
+            return None;
+        }
+        unsafe { Some(&**(*self._metadata)._ptrs.add(index)) }
+    }
+
+    /// Returns a mutable reference to the item at `index` if the arena is alive and the index is in bounds.
+    ///
+    /// This is the `Vec::get_mut` of this array, see `get_at`.
+    pub fn get_at_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len()? {
+            return None;
+        }
+        unsafe { Some(&mut **(*self._metadata)._ptrs.add(index)) }
+    }
+
+    /// Returns the first item if the arena is alive and the array is not empty.
+    pub fn first(&self) -> Option<&T> {
+        self.get_at(0)
+    }
+
+    /// Returns the mutable first item if the arena is alive and the array is not empty.
+    pub fn first_mut(&mut self) -> Option<&mut T> {
+        self.get_at_mut(0)
+    }
+
+    /// Returns the last item if the arena is alive and the array is not empty.
+    pub fn last(&self) -> Option<&T> {
+        self.get_at(self.len()?.checked_sub(1)?)
+    }
+
+    /// Returns the mutable last item if the arena is alive and the array is not empty.
+    pub fn last_mut(&mut self) -> Option<&mut T> {
+        let index = self.len()?.checked_sub(1)?;
+        self.get_at_mut(index)
+    }
+
     /// Removes and returns the last item from the array.
     pub fn pop(&mut self) -> Option<T> {
         if !self._arena.is_alive() {
@@ -164,8 +522,29 @@ impl<T> Array<T> {
                 return None;
             }
             meta._len -= 1;
-            let item_ptr = *meta._ptrs.add(meta._len);
-            Some(std::ptr::read(item_ptr))
+            // the slot stays in the table after the items, for reuse
+            meta._free += 1;
+            Some(Self::take_item(*meta._ptrs.add(meta._len)))
+        }
+    }
+
+    /// Returns a weak handle to the item at `index` if the arena is alive and the index is in bounds.
+    ///
+    /// The handle returns nothing after the item is removed from the array, even if the
+    /// array has a new item at the same index.
+    #[track_caller]
+    pub fn weak_at(&self, index: usize) -> Option<WeakItem<T>> {
+        if index >= self.len()? {
+            return None;
+        }
+        unsafe {
+            let item_ptr = *(*self._metadata)._ptrs.add(index);
+            Some(WeakItem {
+                _arena: self._arena.clone(),
+                _array: self._metadata,
+                _slot: ArraySlot::from_value_ptr(item_ptr),
+                _generation: (*ArraySlot::from_value_ptr(item_ptr)).generation,
+            })
         }
     }
 
@@ -190,6 +569,54 @@ impl<T> Array<T> {
     }
 }
 
+/// A weak handle to an item of `Array`, returned by `Array::weak_at`.
+///
+/// It keeps the generation stamp of the item, and returns nothing after the arena is dead
+/// or the item was removed from the array. The item is accessed over the array that owns it,
+/// so that the access follows the borrow rules of the array.
+pub struct WeakItem<T> {
+    _arena: WeakArena,
+    _array: *mut GrowableArrayMetadata<T>,
+    _slot: *mut ArraySlot<T>,
+    _generation: u64,
+}
+
+impl<T> WeakItem<T> {
+    /// Returns true if the arena is alive and the item was not removed.
+    pub fn is_alive(&self) -> bool {
+        // memory is valid, because this handle still holds the weak arena
+        self._arena.is_alive() && unsafe { (*self._slot).generation } == self._generation
+    }
+
+    /// Returns a reference to the item if it is alive and belongs to the `array`.
+    pub fn get<'a>(&self, array: &'a Array<T>) -> Option<&'a T> {
+        if !self.is_alive() || self._array != array._metadata {
+            return None;
+        }
+        unsafe { Some(&(*self._slot).value) }
+    }
+
+    /// Returns a mutable reference to the item if it is alive and belongs to the `array`.
+    pub fn get_mut<'a>(&self, array: &'a mut Array<T>) -> Option<&'a mut T> {
+        if !self.is_alive() || self._array != array._metadata {
+            return None;
+        }
+        unsafe { Some(&mut (*self._slot).value) }
+    }
+}
+
+impl<T> Clone for WeakItem<T> {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        WeakItem {
+            _arena: self._arena.clone(),
+            _array: self._array,
+            _slot: self._slot,
+            _generation: self._generation,
+        }
+    }
+}
+
 /// Iterator over shared references in an `Array<T>`.
 pub struct ArrayIter<'a, T> {
     array: &'a Array<T>,
@@ -248,6 +675,60 @@ impl<'a, T> Iterator for ArrayIterMut<'a, T> {
 
 impl<'a, T> ExactSizeIterator for ArrayIterMut<'a, T> {}
 
+/// Iterator that removes items from an `Array<T>`, returned by `Array::drain`.
+pub struct ArrayDrain<'a, T> {
+    array: &'a mut Array<T>,
+    index: usize,
+    end: usize,
+    tail_len: usize,
+    // removed item slots that were kept after the items before the drain
+    free: usize,
+}
+
+impl<'a, T> Iterator for ArrayDrain<'a, T> {
+    type Item = T;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.index >= self.end {
+            None
+        } else {
+            unsafe {
+                let item_ptr = *(*self.array._metadata)._ptrs.add(self.index);
+                self.index += 1;
+                Some(Array::take_item(item_ptr))
+            }
+        }
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.end - self.index;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for ArrayDrain<'a, T> {}
+
+impl<'a, T> Drop for ArrayDrain<'a, T> {
+    fn drop(&mut self) {
+        if !self.array._arena.is_alive() {
+            return;
+        }
+        unsafe {
+            while self.index < self.end {
+                let item_ptr = *(*self.array._metadata)._ptrs.add(self.index);
+                self.index += 1;
+                Array::drop_item(item_ptr);
+            }
+            let meta = &mut *self.array._metadata;
+            let start = meta._len;
+            let drained = self.end - start;
+            // move the drained slots after the tail items, followed by the slots that were free before
+            std::slice::from_raw_parts_mut(meta._ptrs.add(start), drained + self.tail_len)
+                .rotate_left(drained);
+            meta._len = start + self.tail_len;
+            meta._free = self.free + drained;
+        }
+    }
+}
+
 impl<T> Index<usize> for Array<T> {
     type Output = T;
     fn index(&self, index: usize) -> &Self::Output {
@@ -364,4 +845,157 @@ mod tests {
         assert_eq!(*flag1.borrow(), true);
         assert_eq!(*flag2.borrow(), true);
     }
+
+    #[test]
+    fn test_weak_items_detect_popped_items() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let mut array = Array::from_iter(&arena, 0..3).unwrap();
+        let first = array.weak_at(0).unwrap();
+        let last = array.weak_at(2).unwrap();
+        assert!(array.weak_at(3).is_none());
+
+        *last.get_mut(&mut array).unwrap() = 5;
+        assert_eq!(Some(&5), last.get(&array));
+
+        assert_eq!(Some(5), array.pop());
+        assert!(!last.is_alive());
+        assert_eq!(None, last.get(&array));
+
+        array.push(6).unwrap();
+        assert_eq!(None, last.get(&array), "new item at the same index is not the same item");
+        assert_eq!(Some(&0), first.get(&array));
+
+        let other = Array::from_iter(&arena, 0..3).unwrap();
+        drop(arena);
+        assert!(!first.is_alive());
+        assert_eq!(None, first.get(&other));
+    }
+
+    #[test]
+    fn test_abandoned_tables_and_removed_slots_are_reused() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let mut array = Array::with_capacity(&arena, 4).unwrap();
+        let first_table = unsafe { (*array._metadata)._ptrs };
+        array.extend(0..5u64).unwrap();
+        assert_eq!(32, arena.retention().reusable_bytes, "first table is abandoned");
+
+        let other = Array::<u64>::with_capacity(&arena, 4).unwrap();
+        assert_eq!(first_table, unsafe { (*other._metadata)._ptrs });
+        assert_eq!(0, arena.retention().reusable_bytes);
+
+        let removed_slot = array.get_at(1).unwrap() as *const u64;
+        let weak = array.weak_at(1).unwrap();
+        assert_eq!(Some(1), array.remove(1));
+        array.push(10).unwrap();
+        assert_eq!(removed_slot, array.last().unwrap() as *const u64);
+        assert!(!weak.is_alive(), "reused slot has a new generation");
+        assert_eq!(vec![0, 2, 3, 4, 10], array.to_vec());
+
+        let mut removed_slots: Vec<_> = array.iter().filter(|v| (2..=4).contains(*v)).map(|v| v as *const u64).collect();
+        drop(array.drain(1..3));
+        array.retain(|v| *v != 4);
+        array.extend(vec![20, 21, 22]).unwrap();
+        assert_eq!(vec![0, 10, 20, 21, 22], array.to_vec());
+        let mut reused_slots: Vec<_> = array.iter().skip(2).map(|v| v as *const u64).collect();
+        removed_slots.sort();
+        reused_slots.sort();
+        assert_eq!(removed_slots, reused_slots);
+    }
+
+    #[test]
+    fn test_vec_like_editing() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let mut array = Array::from_iter(&arena, 0..5).unwrap();
+        array.insert(0, 10).unwrap();
+        array.insert(6, 11).unwrap();
+        assert_eq!(vec![10, 0, 1, 2, 3, 4, 11], array.to_vec());
+
+        assert_eq!(Some(1), array.remove(2));
+        assert_eq!(Some(10), array.swap_remove(0));
+        assert_eq!(vec![11, 0, 2, 3, 4], array.to_vec());
+
+        array.swap(0, 4);
+        array.reverse();
+        assert_eq!(vec![11, 3, 2, 0, 4], array.to_vec());
+
+        array.extend(vec![7, 8]).unwrap();
+        array.retain(|v| v % 2 == 0);
+        assert_eq!(vec![2, 0, 4, 8], array.to_vec());
+        assert_eq!((Some(&2), Some(&8)), (array.first(), array.last()));
+        *array.last_mut().unwrap() = 9;
+        assert_eq!(Some(&9), array.get_at(3));
+        assert_eq!(None, array.get_at(4));
+
+        array.truncate(2);
+        assert_eq!(vec![2, 0], array.to_vec());
+        array.clear();
+        assert!(array.is_empty());
+        assert_eq!(None, array.first());
+    }
+
+    #[test]
+    fn test_sort_does_not_move_items() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let mut array = Array::from_iter(&arena, vec![(3, "c"), (1, "a"), (2, "b"), (1, "d")]).unwrap();
+        let item_ptr = array.get_at(0).unwrap() as *const (i32, &str);
+        let weak = array.weak_at(0).unwrap();
+
+        array.sort_by_key(|(key, _)| *key);
+        assert_eq!(vec![(1, "a"), (1, "d"), (2, "b"), (3, "c")], array.to_vec(), "stable sort");
+        assert_eq!(item_ptr, array.last().unwrap() as *const (i32, &str));
+        assert_eq!(Some(&(3, "c")), weak.get(&array));
+
+        array.sort_by(|a, b| b.1.cmp(a.1));
+        assert_eq!(vec![(1, "d"), (3, "c"), (2, "b"), (1, "a")], array.to_vec());
+    }
+
+    #[test]
+    fn test_drain_and_removals_drop_items() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let flags: Vec<_> = (0..6).map(|_| DropFlag::new(RefCell::new(false))).collect();
+        let mut array = Array::from_iter(&arena, flags.iter().map(|f| Droppable { dropflag: f.clone() })).unwrap();
+        let weak = array.weak_at(1).unwrap();
+
+        {
+            let mut drain = array.drain(1..4);
+            assert_eq!(3, drain.len());
+            let first = drain.next().unwrap();
+            assert!(!weak.is_alive());
+            drop(first);
+            assert!(*flags[1].borrow());
+            assert!(!*flags[2].borrow());
+        }
+        assert!(*flags[2].borrow());
+        assert!(*flags[3].borrow());
+        assert_eq!(Some(3), array.len());
+
+        let mut index = 0;
+        array.retain(|_| { index += 1; index != 2 });
+        assert!(*flags[4].borrow());
+        assert!(!*flags[5].borrow());
+
+        drop(array.drain(..));
+        assert!(*flags[0].borrow());
+        assert!(*flags[5].borrow());
+        assert!(array.is_empty());
+    }
+
+    #[test]
+    fn test_editing_fails_when_arena_is_dead() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let mut array = Array::from_iter(&arena, 0..3).unwrap();
+        drop(arena);
+
+        assert!(matches!(array.push(3), Err(UploadError::ArenaIsNotAlive)));
+        assert!(matches!(array.insert(0, 3), Err(UploadError::ArenaIsNotAlive)));
+        assert!(matches!(array.extend(3..5), Err(UploadError::ArenaIsNotAlive)));
+        assert!(array.get_at(0).is_none());
+        assert!(array.pop().is_none());
+    }
 }
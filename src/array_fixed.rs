@@ -1,4 +1,5 @@
-use crate::{Arena, UploadError, WeakArena};
+use crate::{Arena, UploadError, WeakArena, Guard, GuardMut, Array};
+use crate::array::ArraySlot;
 use crate::dontdothis::{next_item_aligned_start, value_as_slice};
 use std::ptr::{null_mut};
 use crate::iter::EmptyIfDeadIter;
@@ -57,11 +58,54 @@ impl<T> FixedArray<T> where T: Sized {
         }
     }
 
+    /// Returns guarded array items if the arena is alive. The guard keeps the arena alive until it is dropped.
+    pub fn get(&self) -> Option<Guard<'_, [T]>> {
+        let arena = self._arena.arena()?;
+        Some(Guard::new(arena, self.as_ref()))
+    }
+
+    /// Returns guarded mutable array items if the arena is alive. The guard keeps the arena alive until it is dropped.
+    pub fn get_mut(&mut self) -> Option<GuardMut<'_, [T]>> {
+        let arena = self._arena.arena()?;
+        Some(GuardMut::new(arena, self.as_mut()))
+    }
+
     /// Returns true if arena is dead or array is empty.
     pub fn is_empty(&self) -> bool {
         self.len().unwrap_or(0) == 0
     }
 
+    /// Moves the items to a new growable `Array` in the same arena.
+    ///
+    /// The items are dropped only by the returned array, and the memory of this array is
+    /// left behind in arena. If the arena is dead or the new array can not be placed in arena,
+    /// the items stay where they are and are dropped together with the arena.
+    #[track_caller]
+    pub fn into_array(self) -> Result<Array<T>, UploadError> {
+        let arena = self._arena.arena().ok_or(UploadError::ArenaIsNotAlive)?;
+        unsafe {
+            let len = (*self._metadata)._len;
+            let data = (*self._metadata)._data;
+            let array = Array::<T>::with_capacity(&arena, len.max(4))?;
+            let slots = arena.alloc_no_drop_items_aligned_uninit::<ArraySlot<T>>(len, std::mem::size_of::<ArraySlot<T>>())?;
+
+            let meta = &mut *array._metadata;
+            for i in 0..len {
+                let slot = slots.add(i);
+                std::ptr::write(slot, ArraySlot {
+                    generation: arena.next_generation(),
+                    value: std::ptr::read(data.add(i)),
+                });
+                *meta._ptrs.add(i) = std::ptr::addr_of_mut!((*slot).value);
+            }
+            meta._len = len;
+            // nothing is left to drop in this array, and the items are no longer used
+            (*self._metadata)._len = 0;
+            arena.abandon_no_drop_items(data, len, std::mem::size_of::<T>());
+            Ok(array)
+        }
+    }
+
     /// Copies data into Vec
     pub fn to_vec(&self) -> Vec<T>
     where
@@ -76,6 +120,7 @@ impl<T> FixedArray<T> where T: Sized {
     ///
     /// Once the items are initialized, this array can be converted to `Array` type.
     /// The total number of items in array can not exceed the initial capacity.
+    #[track_caller]
     pub fn with_capacity(arena: &Arena, capacity: usize) -> Result<UninitArray<T>, UploadError> {
         unsafe {
             let metadata = arena.upload_no_drop::<ArrayMetadata<T>>(ArrayMetadata::<T> {
@@ -89,7 +134,7 @@ impl<T> FixedArray<T> where T: Sized {
             (*metadata)._data = ptr as *mut T;
 
             Ok(UninitArray {
-                _arena: arena.to_weak_arena(),
+                _arena: arena.to_weak_arena_for::<UninitArray<T>>(),
                 _capacity: capacity,
                 _metadata: metadata,
             })
@@ -97,6 +142,7 @@ impl<T> FixedArray<T> where T: Sized {
     }
 
     /// Creates a new array and places the data to it.
+    #[track_caller]
     pub fn new(arena: &Arena, iter: impl ExactSizeIterator<Item=T>) -> Result<FixedArray<T>, UploadError> {
         unsafe {
             let len = iter.len();
@@ -132,7 +178,7 @@ impl<T> FixedArray<T> where T: Sized {
             }
 
             Ok(FixedArray {
-                _arena: arena.to_weak_arena(),
+                _arena: arena.to_weak_arena_for::<Self>(),
                 _metadata: metadata,
             })
         }
@@ -328,7 +374,9 @@ impl<T> PartialEq for FixedArray<T> where T: PartialEq, T: Sized {
 
 #[cfg(test)]
 mod array {
-    use crate::{Memory, Arena, FixedArray, MemurIterator};
+    use crate::{Memory, Arena, FixedArray, Array, MemurIterator};
+    use crate::dropflag::DropFlag;
+    use std::cell::RefCell;
 
     #[test]
     fn has_items_when_iterating() {
@@ -402,4 +450,48 @@ mod array {
         assert_eq!(0, sum);
         assert_eq!(None, items.len());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn converts_between_fixed_and_growable_array() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let mut array = Array::from_iter(&arena, 0..6).unwrap();
+        array.remove(0);
+        let weak = array.weak_at(0).unwrap();
+
+        let fixed = array.into_fixed_array().unwrap();
+        assert_eq!(&[1, 2, 3, 4, 5], fixed.as_ref());
+        assert!(!weak.is_alive(), "item was moved out of the array");
+
+        let mut array = fixed.into_array().unwrap();
+        array.push(6).unwrap();
+        assert_eq!(vec![1, 2, 3, 4, 5, 6], array.to_vec());
+    }
+
+    #[test]
+    fn converted_items_are_dropped_once() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let flags: Vec<_> = (0..3).map(|_| DropFlag::new(RefCell::new(0))).collect();
+        let items = flags.iter().map(|f| Counted { drops: f.clone() });
+
+        let fixed = Array::from_iter(&arena, items).unwrap().into_fixed_array().unwrap();
+        let mut array = fixed.into_array().unwrap();
+        drop(array.pop());
+        assert_eq!(1, *flags[2].borrow());
+        assert_eq!(0, *flags[0].borrow());
+
+        drop(arena);
+        assert!(flags.iter().all(|flag| *flag.borrow() == 1));
+    }
+
+    struct Counted {
+        drops: DropFlag<i32>,
+    }
+
+    impl Drop for Counted {
+        fn drop(&mut self) {
+            *self.drops.borrow_mut() += 1;
+        }
+    }
+}
@@ -66,6 +66,25 @@ impl Block {
         }
     }
 
+    /// Returns the size of the block data needed to place a single item of `len` bytes.
+    pub fn size_for_item(len: usize) -> usize {
+        std::mem::size_of::<BlockMetadata>() + len
+    }
+
+    pub fn size(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn previous_block(&self) -> Option<&Block> {
+        let metadata = unsafe { BlockMetadata::reinterpret_from_slice(&self.data) };
+        metadata.previous_block.as_ref()
+    }
+
+    pub unsafe fn take_previous_block(&mut self) -> Option<Block> {
+        let metadata = BlockMetadata::reinterpret_from_slice_mut(&mut self.data);
+        metadata.previous_block.take()
+    }
+
     pub unsafe fn set_previous_block(&mut self, block: Block) {
         let metadata = BlockMetadata::reinterpret_from_slice_mut(&mut *self.data);
         metadata.previous_block = Some(block);
@@ -119,6 +138,24 @@ impl Block {
         (self.data.len() as isize - aligned as isize, aligned)
     }
 
+    /// Changes the size of the last item in block if it starts at `item` and has `len` bytes,
+    /// and the block has enough space for `new_len` bytes. Returns false if the item can not be resized.
+    pub unsafe fn try_resize_last_item(&mut self, item: *const u8, len: usize, new_len: usize) -> bool {
+        let data_start = self.data.as_ptr() as usize;
+        let data_end = data_start + self.data.len();
+        let item_start = item as usize;
+        if item_start < data_start || item_start >= data_end {
+            return false;
+        }
+        let metadata = BlockMetadata::reinterpret_from_slice_mut(&mut self.data);
+        let item_offset = item_start - data_start;
+        if metadata.next_item_offset != item_offset + len || new_len > self.data.len() - item_offset {
+            return false;
+        }
+        metadata.next_item_offset = item_offset + new_len;
+        true
+    }
+
     pub unsafe fn upload_bytes_unchecked(&mut self, aligned_start: usize, len: usize, value: impl Iterator<Item=u8>) -> *mut u8 {
         let metadata = BlockMetadata::reinterpret_from_slice_mut(&mut *self.data);
         let end = aligned_start + len;
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::panic::Location;
+
+/// A `WeakArena` reference that keeps the arena memory from being returned to `Memory`.
+#[derive(Debug, Clone)]
+pub struct WeakHolder {
+    /// Address of the arena, the same for all holders of the same arena.
+    pub arena: usize,
+    /// Type of the handle that holds the reference, i.e. `memur::ustr::UStr`.
+    pub handle_type: &'static str,
+    /// Location in code where the reference was created.
+    pub location: &'static Location<'static>,
+}
+
+impl Display for WeakHolder {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} created at {}", self.handle_type, self.location)
+    }
+}
+
+/// Outstanding `WeakArena` references of all arenas of a `Memory`.
+#[derive(Default)]
+pub (crate) struct WeakHolders {
+    next_id: u64,
+    holders: HashMap<u64, WeakHolder>,
+}
+
+impl WeakHolders {
+    pub fn register(&mut self, holder: WeakHolder) -> u64 {
+        self.next_id += 1;
+        self.holders.insert(self.next_id, holder);
+        self.next_id
+    }
+
+    pub fn unregister(&mut self, id: u64) {
+        self.holders.remove(&id);
+    }
+
+    pub fn handle_type(&self, id: u64) -> &'static str {
+        self.holders.get(&id).map(|h| h.handle_type).unwrap_or("memur::arena::WeakArena")
+    }
+
+    /// Returns holders of the arena, or of all arenas if `arena` is `None`, in creation order.
+    pub fn list(&self, arena: Option<usize>) -> Vec<WeakHolder> {
+        let mut holders: Vec<_> = self.holders.iter()
+            .filter(|(_, h)| arena.map(|a| a == h.arena).unwrap_or(true))
+            .collect();
+        holders.sort_by_key(|(id, _)| **id);
+        holders.into_iter().map(|(_, h)| h.clone()).collect()
+    }
+}
+
+#[cfg(test)]
+mod diagnostics_tests {
+    use crate::{Memory, Arena, UStr, N, List};
+    use std::panic::Location;
+
+    #[test]
+    fn lists_outstanding_weak_holders() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let other = Arena::new(&mem).unwrap();
+        let (name, name_location) = (UStr::from_str(&arena, "name").unwrap(), Location::caller());
+        let value = N::new(&arena, 1).unwrap();
+        let list = List::<i32>::new(&other).unwrap();
+
+        let holders = arena.weak_holders();
+        assert_eq!(2, holders.len());
+        assert_eq!("memur::ustr::UStr", holders[0].handle_type);
+        assert_eq!("memur::n::N<i32>", holders[1].handle_type);
+        assert_eq!(name_location.file(), holders[0].location.file());
+        assert_eq!(name_location.line(), holders[0].location.line());
+        assert_eq!(3, mem.weak_holders().len());
+
+        let (name_clone, clone_location) = (name.clone(), Location::caller());
+        assert_eq!("memur::ustr::UStr", arena.weak_holders()[2].handle_type);
+        assert_eq!(clone_location.line(), arena.weak_holders()[2].location.line());
+
+        drop(arena);
+        drop(value);
+        drop(name);
+        let holders = mem.weak_holders();
+        assert_eq!(2, holders.len());
+        assert!(holders[0].to_string().starts_with("memur::list::List<"));
+        assert!(holders[1].to_string().starts_with("memur::ustr::UStr created at "));
+
+        drop(name_clone);
+        drop(list);
+        drop(other);
+        assert!(mem.weak_holders().is_empty());
+    }
+}
@@ -115,6 +115,26 @@ impl DropItem {
     }
 }
 
+/// Drop function that is linked to the next one, used for hooks that run after all droplists
+/// are executed.
+pub struct DropHook {
+    pub item: DropItem,
+    pub next: *mut DropHook,
+}
+
+impl DropHook {
+    /// Executes this hook and all hooks linked after it, in the order they were linked.
+    pub unsafe fn execute_hook_chain(&mut self) {
+        let mut head: *mut DropHook = self;
+        while !head.is_null() {
+            (*head).item.execute();
+            let next = (*head).next;
+            (*head).next = std::ptr::null_mut();
+            head = next;
+        }
+    }
+}
+
 /// Function that is intended to drop the values at the specified pointer location.
 ///
 /// Drop functions are placed in droplists, and droplists are executed when the arena is dropped.
@@ -0,0 +1,225 @@
+use crate::Arena;
+use std::ops::{Deref, DerefMut};
+use std::fmt::{Debug, Display, Formatter};
+use std::cell::Cell;
+use std::mem::ManuallyDrop;
+
+/// A reference to the value in arena that keeps the `Arena` alive for as long as the guard exists.
+///
+/// If the last other `Arena` is dropped while the guard is alive, the drop functions are executed
+/// only when the guard is dropped.
+///
+/// If the value has a borrow flag (like `N`), the value is also marked as borrowed until the guard is dropped.
+pub struct Guard<'a, T> where T: ?Sized {
+    // the reference goes out of scope before the arena
+    value: &'a T,
+    borrow: Option<&'a Cell<isize>>,
+    _arena: Arena,
+}
+
+impl<'a, T> Guard<'a, T> where T: ?Sized {
+    #[inline(always)]
+    pub (crate) fn new(arena: Arena, value: &'a T) -> Guard<'a, T> {
+        Guard {
+            value,
+            borrow: None,
+            _arena: arena,
+        }
+    }
+
+    /// Creates a guard that increments the shared borrow count in the `borrow` flag until it is dropped.
+    /// The caller must ensure the value is not borrowed mutably.
+    #[inline(always)]
+    pub (crate) fn new_borrowed(arena: Arena, value: &'a T, borrow: &'a Cell<isize>) -> Guard<'a, T> {
+        debug_assert!(borrow.get() >= 0, "value is not borrowed mutably");
+        borrow.set(borrow.get() + 1);
+        Guard {
+            value,
+            borrow: Some(borrow),
+            _arena: arena,
+        }
+    }
+
+    /// Returns a guard to a part of the guarded value, i.e. a field of a struct.
+    pub fn map<U, F>(guard: Guard<'a, T>, fun: F) -> Guard<'a, U> where U: ?Sized, F: FnOnce(&'a T) -> &'a U {
+        let guard = ManuallyDrop::new(guard);
+        Guard {
+            value: fun(guard.value),
+            borrow: guard.borrow,
+            _arena: unsafe { std::ptr::read(&guard._arena) },
+        }
+    }
+}
+
+impl<'a, T> Drop for Guard<'a, T> where T: ?Sized {
+    fn drop(&mut self) {
+        if let Some(borrow) = self.borrow {
+            borrow.set(borrow.get() - 1);
+        }
+    }
+}
+
+impl<'a, T> Deref for Guard<'a, T> where T: ?Sized {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T> Debug for Guard<'a, T> where T: Debug + ?Sized {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(self.value, f)
+    }
+}
+
+impl<'a, T> Display for Guard<'a, T> where T: Display + ?Sized {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self.value, f)
+    }
+}
+
+/// A mutable reference to the value in arena that keeps the `Arena` alive for as long as the guard exists.
+///
+/// If the value has a borrow flag (like `N`), the value is also marked as mutably borrowed until the guard is dropped.
+pub struct GuardMut<'a, T> where T: ?Sized {
+    // the reference goes out of scope before the arena
+    value: &'a mut T,
+    borrow: Option<&'a Cell<isize>>,
+    _arena: Arena,
+}
+
+impl<'a, T> GuardMut<'a, T> where T: ?Sized {
+    #[inline(always)]
+    pub (crate) fn new(arena: Arena, value: &'a mut T) -> GuardMut<'a, T> {
+        GuardMut {
+            value,
+            borrow: None,
+            _arena: arena,
+        }
+    }
+
+    /// Creates a guard that marks the `borrow` flag as mutably borrowed until it is dropped.
+    /// The caller must ensure the value is not borrowed.
+    #[inline(always)]
+    pub (crate) fn new_borrowed(arena: Arena, value: &'a mut T, borrow: &'a Cell<isize>) -> GuardMut<'a, T> {
+        debug_assert_eq!(0, borrow.get(), "value is not borrowed");
+        borrow.set(-1);
+        GuardMut {
+            value,
+            borrow: Some(borrow),
+            _arena: arena,
+        }
+    }
+
+    /// Returns a guard to a part of the guarded value, i.e. a field of a struct.
+    pub fn map<U, F>(guard: GuardMut<'a, T>, fun: F) -> GuardMut<'a, U> where U: ?Sized, F: FnOnce(&'a mut T) -> &'a mut U {
+        let guard = ManuallyDrop::new(guard);
+        let value = unsafe { std::ptr::read(&guard.value) };
+        GuardMut {
+            value: fun(value),
+            borrow: guard.borrow,
+            _arena: unsafe { std::ptr::read(&guard._arena) },
+        }
+    }
+}
+
+impl<'a, T> Drop for GuardMut<'a, T> where T: ?Sized {
+    fn drop(&mut self) {
+        if let Some(borrow) = self.borrow {
+            borrow.set(0);
+        }
+    }
+}
+
+impl<'a, T> Deref for GuardMut<'a, T> where T: ?Sized {
+    type Target = T;
+
+    #[inline(always)]
+    fn deref(&self) -> &T {
+        self.value
+    }
+}
+
+impl<'a, T> DerefMut for GuardMut<'a, T> where T: ?Sized {
+    #[inline(always)]
+    fn deref_mut(&mut self) -> &mut T {
+        self.value
+    }
+}
+
+impl<'a, T> Debug for GuardMut<'a, T> where T: Debug + ?Sized {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&*self.value, f)
+    }
+}
+
+#[cfg(test)]
+mod guard_tests {
+    use crate::{Memory, Arena, N, UStr, List, Array, FixedArray, Guard};
+    use crate::dropflag::{Droppable, DropFlag};
+    use std::cell::RefCell;
+
+    #[test]
+    fn guard_keeps_arena_alive() {
+        let flag = DropFlag::new(RefCell::new(false));
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let obj = N::new(&arena, Droppable { dropflag: flag.clone() }).unwrap();
+
+        let guard = obj.get().expect("arena is alive");
+        drop(arena);
+
+        assert!(!*guard.dropflag.borrow(), "value is not dropped while guard is alive");
+        drop(guard);
+
+        assert!(*flag.borrow(), "value is dropped with the guard");
+        assert!(obj.get().is_none(), "value can not be accessed");
+    }
+
+    #[test]
+    fn guard_mut_modifies_value() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut obj = N::new(&arena, (1, 2)).unwrap();
+
+        *obj.get_mut().unwrap() = (3, 4);
+        let second = Guard::map(obj.get().unwrap(), |v| &v.1);
+
+        assert_eq!(4, *second);
+    }
+
+    #[test]
+    fn all_handles_return_none_when_arena_is_dead() {
+        let mem = Memory::new();
+        let (mut n, s, mut l, mut a, mut f) = {
+            let arena = Arena::new(&mem).unwrap();
+            let n = N::new(&arena, 1).unwrap();
+            let s = UStr::from_str(&arena, "hello").unwrap();
+            let l = List::from_iter(&arena, 0..3).unwrap();
+            let mut a = Array::from_iter(&arena, 0..3).unwrap();
+            let f = FixedArray::new(&arena, 0..3).unwrap();
+
+            assert_eq!(1, *n.get().unwrap());
+            assert_eq!("hello", &*s.get().unwrap());
+            assert_eq!(3, l.get().unwrap().iter().count());
+            assert_eq!(2, a.get().unwrap()[2]);
+            assert_eq!(&[0, 1, 2], &*f.get().unwrap());
+            a.get_mut().unwrap()[2] = 5;
+            assert_eq!(5, a[2]);
+
+            (n, s, l, a, f)
+        };
+
+        assert!(n.get().is_none());
+        assert!(n.get_mut().is_none());
+        assert!(s.get().is_none());
+        assert!(l.get().is_none());
+        assert!(l.get_mut().is_none());
+        assert!(a.get().is_none());
+        assert!(a.get_mut().is_none());
+        assert!(f.get().is_none());
+        assert!(f.get_mut().is_none());
+    }
+}
@@ -0,0 +1,313 @@
+use crate::{Arena, WeakArena, UploadError, UStr, UStrError};
+use std::collections::hash_map::DefaultHasher;
+use std::ffi::CStr;
+use std::hash::Hasher;
+
+/// Compact identifier of a string interned in `UStrInterner`.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Returns the index of the string in the interner, in the order strings were interned.
+    pub fn as_u32(&self) -> u32 {
+        self.0
+    }
+}
+
+/// Interned string in arena.
+#[derive(Copy, Clone)]
+struct InternerEntry {
+    first: *mut u8,
+    len: u32,
+}
+
+/// Slot of the open addressing hash table. The `symbol` is the index of entry plus one,
+/// or 0 if the slot is empty.
+#[derive(Copy, Clone)]
+struct InternerSlot {
+    hash: u64,
+    symbol: u32,
+}
+
+/// Arena‐uploaded hash table of the interner.
+struct InternerTable {
+    // power of two
+    slot_count: usize,
+    slots: *mut InternerSlot,
+    entry_count: usize,
+    entry_capacity: usize,
+    entries: *mut InternerEntry,
+}
+
+impl InternerTable {
+    #[inline(always)]
+    fn hash(bytes: &[u8]) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(bytes);
+        hasher.finish()
+    }
+
+    unsafe fn entry_bytes(&self, symbol: u32) -> &[u8] {
+        let entry = *self.entries.add(symbol as usize - 1);
+        std::slice::from_raw_parts(entry.first, entry.len as usize)
+    }
+
+    /// Returns the slot index that contains the bytes, or the empty slot where they should be placed.
+    unsafe fn find_slot(&self, hash: u64, bytes: &[u8]) -> usize {
+        let mask = self.slot_count - 1;
+        let mut index = hash as usize & mask;
+        loop {
+            let slot = *self.slots.add(index);
+            if slot.symbol == 0 || (slot.hash == hash && self.entry_bytes(slot.symbol) == bytes) {
+                return index;
+            }
+            index = (index + 1) & mask;
+        }
+    }
+
+    unsafe fn find(&self, bytes: &[u8]) -> Option<u32> {
+        let slot = *self.slots.add(self.find_slot(InternerTable::hash(bytes), bytes));
+        if slot.symbol == 0 { None } else { Some(slot.symbol) }
+    }
+
+    unsafe fn alloc_slots(arena: &Arena, slot_count: usize) -> Result<*mut InternerSlot, UploadError> {
+        let slots = arena.alloc_no_drop_items_aligned_uninit::<InternerSlot>(slot_count, std::mem::size_of::<InternerSlot>())?;
+        for i in 0..slot_count {
+            std::ptr::write(slots.add(i), InternerSlot { hash: 0, symbol: 0 });
+        }
+        Ok(slots)
+    }
+
+    /// Makes sure there is space for one more entry, keeping the table at most half full.
+    unsafe fn reserve_one(&mut self, arena: &Arena) -> Result<(), UploadError> {
+        if self.entry_count == self.entry_capacity {
+            let new_capacity = self.entry_capacity * 2;
+            let new_entries = arena.alloc_no_drop_items_aligned_uninit::<InternerEntry>(new_capacity, std::mem::size_of::<InternerEntry>())?;
+            std::ptr::copy_nonoverlapping(self.entries, new_entries, self.entry_count);
+            arena.abandon_no_drop_items(self.entries, self.entry_capacity, std::mem::size_of::<InternerEntry>());
+            self.entries = new_entries;
+            self.entry_capacity = new_capacity;
+        }
+        if (self.entry_count + 1) * 2 > self.slot_count {
+            let old_slots = self.slots;
+            let old_slot_count = self.slot_count;
+            self.slots = InternerTable::alloc_slots(arena, old_slot_count * 2)?;
+            self.slot_count = old_slot_count * 2;
+            for i in 0..old_slot_count {
+                let slot = *old_slots.add(i);
+                if slot.symbol != 0 {
+                    let mask = self.slot_count - 1;
+                    let mut index = slot.hash as usize & mask;
+                    while (*self.slots.add(index)).symbol != 0 {
+                        index = (index + 1) & mask;
+                    }
+                    *self.slots.add(index) = slot;
+                }
+            }
+            arena.abandon_no_drop_items(old_slots, old_slot_count, std::mem::size_of::<InternerSlot>());
+        }
+        Ok(())
+    }
+}
+
+/// Deduplicates strings stored in arena.
+///
+/// Equal strings interned in the same interner return `UStr` that points to the same memory,
+/// so they are compared by pointer, or a `Symbol` that can be resolved back to `UStr`.
+/// The lookup by `&str` or `&CStr` does not allocate.
+///
+/// ```rust
+/// use memur::{Memory, Arena, UStrInterner};
+///
+/// let mem = Memory::new();
+/// let arena = Arena::new(&mem).unwrap();
+/// let interner = UStrInterner::new(&arena).unwrap();
+///
+/// let a = interner.intern("position").unwrap();
+/// let b = interner.intern("position").unwrap();
+/// assert_eq!(a.as_ptr(), b.as_ptr());
+///
+/// let symbol = interner.intern_symbol("normal").unwrap();
+/// assert_eq!(Some(symbol), interner.symbol("normal"));
+/// assert_eq!("normal", &interner.resolve(symbol).unwrap());
+/// ```
+pub struct UStrInterner {
+    _arena: WeakArena,
+    _table: *mut InternerTable,
+}
+
+impl UStrInterner {
+    /// Creates a new empty interner in arena.
+    #[track_caller]
+    pub fn new(arena: &Arena) -> Result<UStrInterner, UploadError> {
+        UStrInterner::with_capacity(arena, 16)
+    }
+
+    /// Creates a new interner that has space for `capacity` strings before growing.
+    #[track_caller]
+    pub fn with_capacity(arena: &Arena, capacity: usize) -> Result<UStrInterner, UploadError> {
+        let capacity = capacity.max(1);
+        unsafe {
+            let slots = InternerTable::alloc_slots(arena, (capacity * 2).next_power_of_two())?;
+            let entries = arena.alloc_no_drop_items_aligned_uninit::<InternerEntry>(capacity, std::mem::size_of::<InternerEntry>())?;
+            let table = arena.upload_no_drop(InternerTable {
+                slot_count: (capacity * 2).next_power_of_two(),
+                slots,
+                entry_count: 0,
+                entry_capacity: capacity,
+                entries,
+            })?;
+            Ok(UStrInterner {
+                _arena: arena.to_weak_arena_for::<Self>(),
+                _table: table,
+            })
+        }
+    }
+
+    /// Returns the number of interned strings, or `None` if the arena is dead.
+    pub fn len(&self) -> Option<usize> {
+        if self._arena.is_alive() {
+            unsafe { Some((*self._table).entry_count) }
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if there are no interned strings (or if the arena is dead).
+    pub fn is_empty(&self) -> bool {
+        self.len().unwrap_or(0) == 0
+    }
+
+    /// Returns the interned string, uploading it to arena if it was not interned yet.
+    #[track_caller]
+    pub fn intern(&self, value: &str) -> Result<UStr, UStrError> {
+        let symbol = self.intern_symbol(value)?;
+        Ok(self.resolve(symbol).expect("interned symbol resolves"))
+    }
+
+    /// Returns the interned string, uploading it to arena if it was not interned yet.
+    #[track_caller]
+    pub fn intern_cstr(&self, value: &CStr) -> Result<UStr, UStrError> {
+        match value.to_str() {
+            Ok(str) => self.intern(str),
+            Err(e) => Err(UStrError::CStrIsNotUtf8(e)),
+        }
+    }
+
+    /// Returns the symbol of the string, uploading it to arena if it was not interned yet.
+    pub fn intern_symbol(&self, value: &str) -> Result<Symbol, UStrError> {
+        let arena = self._arena.arena().ok_or(UploadError::ArenaIsNotAlive)?;
+        unsafe {
+            let table = &mut *self._table;
+            if let Some(symbol) = table.find(value.as_bytes()) {
+                return Ok(Symbol(symbol - 1));
+            }
+
+            let ustr = UStr::from_str(&arena, value)?;
+            table.reserve_one(&arena)?;
+            let hash = InternerTable::hash(value.as_bytes());
+            let index = table.find_slot(hash, value.as_bytes());
+            *table.entries.add(table.entry_count) = InternerEntry {
+                first: ustr.first,
+                len: ustr.byte_count_without_nul,
+            };
+            table.entry_count += 1;
+            *table.slots.add(index) = InternerSlot { hash, symbol: table.entry_count as u32 };
+            Ok(Symbol(table.entry_count as u32 - 1))
+        }
+    }
+
+    /// Returns the interned string if it exists, without uploading it.
+    #[track_caller]
+    pub fn get(&self, value: &str) -> Option<UStr> {
+        self.symbol(value).and_then(|symbol| self.resolve(symbol))
+    }
+
+    /// Returns the interned string if it exists, without uploading it.
+    #[track_caller]
+    pub fn get_cstr(&self, value: &CStr) -> Option<UStr> {
+        self.cstr_symbol(value).and_then(|symbol| self.resolve(symbol))
+    }
+
+    /// Returns the symbol of the string if it is interned.
+    pub fn symbol(&self, value: &str) -> Option<Symbol> {
+        if !self._arena.is_alive() {
+            return None;
+        }
+        unsafe { (*self._table).find(value.as_bytes()).map(|symbol| Symbol(symbol - 1)) }
+    }
+
+    /// Returns the symbol of the string if it is interned.
+    pub fn cstr_symbol(&self, value: &CStr) -> Option<Symbol> {
+        if !self._arena.is_alive() {
+            return None;
+        }
+        unsafe { (*self._table).find(value.to_bytes()).map(|symbol| Symbol(symbol - 1)) }
+    }
+
+    /// Returns the string of the symbol if it was returned by this interner and the arena is alive.
+    #[track_caller]
+    pub fn resolve(&self, symbol: Symbol) -> Option<UStr> {
+        if !self._arena.is_alive() {
+            return None;
+        }
+        unsafe {
+            let table = &*self._table;
+            if symbol.0 as usize >= table.entry_count {
+                return None;
+            }
+            let entry = *table.entries.add(symbol.0 as usize);
+            Some(UStr {
+                _arena: self._arena.clone(),
+                byte_count_without_nul: entry.len,
+                first: entry.first,
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod interner_tests {
+    use crate::{Memory, Arena, UStrInterner, UStr};
+    use std::ffi::CString;
+
+    #[test]
+    fn equal_strings_are_uploaded_once() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let interner = UStrInterner::with_capacity(&arena, 2).unwrap();
+
+        let symbols: Vec<_> = (0..1000).map(|i| interner.intern_symbol(&format!("name{}", i)).unwrap()).collect();
+        for (i, symbol) in symbols.iter().enumerate() {
+            let name = format!("name{}", i);
+            assert_eq!(*symbol, interner.intern_symbol(&name).unwrap());
+            assert_eq!(name.as_str(), &interner.resolve(*symbol).unwrap());
+            assert_eq!(i as u32, symbol.as_u32());
+        }
+        assert_eq!(Some(1000), interner.len());
+
+        let a = interner.intern("name5").unwrap();
+        let b = interner.get_cstr(&CString::new("name5").unwrap()).unwrap();
+        assert_eq!(a.as_ptr(), b.as_ptr());
+        assert_eq!(a, UStr::from_str(&arena, "name5").unwrap());
+    }
+
+    #[test]
+    fn lookup_does_not_intern() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let interner = UStrInterner::new(&arena).unwrap();
+
+        assert!(interner.get("missing").is_none());
+        assert!(interner.symbol("missing").is_none());
+        assert!(interner.is_empty());
+        assert!(interner.intern("with\0nul").is_err());
+        assert!(interner.is_empty());
+
+        let value = interner.intern("value").unwrap();
+        drop(arena);
+        assert!(interner.get("value").is_none());
+        assert!(interner.intern("other").is_err());
+        assert_eq!("value", &value);
+    }
+}
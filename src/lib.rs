@@ -32,6 +32,13 @@
 //!     - Items are allocated individually, so they are not stored contiguously.
 //!     - Pointer indirection incurs a slight overhead compared to a contiguous FixedArray or List.
 //!
+//! - **ArenaVec** – A growable array that stores items contiguously and can be used as a slice.
+//!   It grows in place while it is the last allocation in arena, and converts to `FixedArray`
+//!   without copying.
+//!
+//! - **SegmentedVec** – A growable array that stores items in chunks of doubling size, so the
+//!   items are never moved and are indexed without pointer indirection.
+//!
 //! - **List** – A simple, growable list where items are stored non-contiguously.
 //!   It keeps related metadata close to the data, but it does not support indexing or cloning.
 //!
@@ -129,7 +136,8 @@
 //! assert_eq!(array.pop(), Some(7));
 //! ```
 //!
-//! As mentioned before, no actual memory is reclaimed until the whole Arena is dropped.
+//! As mentioned before, no actual memory is returned to `Memory` until the whole Arena is dropped,
+//! but the slots of removed items and the outgrown pointer tables are reused by the following allocations.
 //!
 //! ## List
 //!
@@ -188,18 +196,36 @@ mod array;
 mod array_fixed;
 mod array_uninit;
 mod ustr;
+mod ubytes;
+mod uwstr;
+mod interner;
+mod arena_string;
+mod arena_vec;
+mod segmented_vec;
 mod n;
 mod traits;
 mod iter;
+mod guard;
+#[cfg(feature = "diagnostics")]
+mod diagnostics;
 
-pub use memory::{Memory, MemoryBuilder};
+pub use memory::{Memory, MemoryBuilder, LeakCheck, LeakReport, LeasedArena};
 pub use list::List;
-pub use array::{Array, ArrayIter, ArrayIterMut};
+pub use array::{Array, ArrayIter, ArrayIterMut, ArrayDrain, WeakItem};
 pub use array_fixed::{FixedArray, ArrayInitializer};
 pub use array_uninit::{UninitArray};
-pub use ustr::{UStr, UStrError};
-pub use arena::{WeakArena, Arena, UploadError};
-pub use n::N;
+pub use ustr::{UStr, UStrSlice, UStrError};
+pub use ubytes::{UBytes, UOsStr, UPath};
+pub use uwstr::UWStr;
+pub use interner::{UStrInterner, Symbol};
+pub use arena_string::ArenaString;
+pub use arena_vec::ArenaVec;
+pub use segmented_vec::SegmentedVec;
+pub use arena::{WeakArena, Arena, ArenaRetention, UploadError, DropPhase, MAX_DROP_PHASES};
+pub use n::{N, NRef, NDyn, NField, NWeak, BorrowError, DropOrderError, DropNode, DropOrdered};
+pub use guard::{Guard, GuardMut};
+#[cfg(feature = "diagnostics")]
+pub use diagnostics::WeakHolder;
 pub use traits::{MemurIterator, ToArenaArray, ToArenaFixedArray, ToArenaList};
 pub use droplist::{DropFn, DropItem};
 
@@ -1,4 +1,4 @@
-use crate::{Arena, WeakArena, UploadError};
+use crate::{Arena, WeakArena, UploadError, Guard, GuardMut};
 use std::ptr::{null_mut};
 
 const MAX_ITEMS: usize = 32;
@@ -40,12 +40,13 @@ pub struct List<T> where T: Sized {
 
 impl<T> List<T> where T: Sized {
     /// Initializes a new list in arena and returns a handle to it.
+    #[track_caller]
     pub fn new(arena: &Arena) -> Result<List<T>, UploadError> {
         unsafe {
             let (starting_sequence, _) = arena.upload_auto_drop(PartialSequence::empty())?;
 
             Ok(List {
-                arena: arena.to_weak_arena(),
+                arena: arena.to_weak_arena_for::<Self>(),
                 _len: 0,
                 _first: starting_sequence,
                 _last: starting_sequence,
@@ -58,6 +59,20 @@ impl<T> List<T> where T: Sized {
         self._len
     }
 
+    /// Returns a guarded reference to this list if the arena is alive. The guard keeps the arena
+    /// alive until it is dropped, so the items can be safely accessed over it.
+    pub fn get(&self) -> Option<Guard<'_, List<T>>> {
+        let arena = self.arena.arena()?;
+        Some(Guard::new(arena, self))
+    }
+
+    /// Returns a guarded mutable reference to this list if the arena is alive. The guard keeps the arena
+    /// alive until it is dropped, so the items can be safely accessed over it.
+    pub fn get_mut(&mut self) -> Option<GuardMut<'_, List<T>>> {
+        let arena = self.arena.arena()?;
+        Some(GuardMut::new(arena, self))
+    }
+
     /// Iterates over the item references in arena, returns no items if the arena is dead.
     #[inline(always)]
     pub fn iter(&self) -> impl ExactSizeIterator<Item=&T> {
@@ -145,6 +160,7 @@ impl<T> List<T> where T: Sized {
     }
 
     /// Creates a new list by consuming an iterator.
+    #[track_caller]
     pub fn from_iter<I: IntoIterator<Item = T>>(arena: &Arena, iter: I) -> Result<Self, UploadError> {
         let iter = iter.into_iter();
         let mut list = List::new(arena)?;
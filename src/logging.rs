@@ -21,4 +21,14 @@ macro_rules! trace {
         #[cfg(feature = "logging")]
         log::trace!($($arg)+);
     )
-}
\ No newline at end of file
+}
+macro_rules! warn {
+    (target: $target:expr, $($arg:tt)+) => (
+        #[cfg(feature = "logging")]
+        log::warn!(target: $target, $($arg)+);
+    );
+    ($($arg:tt)+) => (
+        #[cfg(feature = "logging")]
+        log::warn!($($arg)+);
+    )
+}
@@ -1,10 +1,24 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+#[cfg(feature = "diagnostics")]
+use crate::diagnostics::{WeakHolder, WeakHolders};
 
 struct ArenaMemoryInstance {
     free_blocks: VecDeque<Box<[u8]>>,
     max_free_blocks_to_initialize_or_cleanup_to: i32,
     min_free_blocks_before_allocating_new: i32,
     new_block_size: usize,
+    leak_check: LeakCheck,
+    // blocks taken by arenas and not yet returned, by arena address
+    leases: BTreeMap<usize, BlockLease>,
+}
+
+/// Blocks taken by a single arena.
+#[derive(Default)]
+struct BlockLease {
+    blocks: usize,
+    bytes: usize,
 }
 
 impl ArenaMemoryInstance {
@@ -14,6 +28,8 @@ impl ArenaMemoryInstance {
             max_free_blocks_to_initialize_or_cleanup_to: options.max_free_blocks_to_initialize_or_cleanup_to,
             min_free_blocks_before_allocating_new: options.min_free_blocks_before_allocating_new,
             new_block_size: options.new_block_size,
+            leak_check: options.leak_check,
+            leases: BTreeMap::new(),
         };
         i.check_if_not_enough_blocks_and_initialize();
         i
@@ -49,12 +65,108 @@ impl ArenaMemoryInstance {
         block
     }
 
+    /// Takes a block that has at least `len` bytes. If the `len` is larger than the block size,
+    /// a dedicated block is allocated for it.
+    pub fn take_block_of_size(&mut self, len: usize) -> Box<[u8]> {
+        if len <= self.new_block_size {
+            return self.take_block();
+        }
+
+        debug!("-- alloc  dedicated block of size {}", len);
+
+        vec![0u8; len].into_boxed_slice()
+    }
+
     pub fn return_block(&mut self, block: Box<[u8]>) {
+        if block.len() != self.new_block_size {
+            debug!("-- free   dedicated block of size {}", block.len());
+            return;
+        }
 
         debug!("-- return block of size {}", block.len());
 
         self.free_blocks.push_back(block);
     }
+
+    pub fn record_lease(&mut self, arena: usize, bytes: usize) {
+        let lease = self.leases.entry(arena).or_default();
+        lease.blocks += 1;
+        lease.bytes += bytes;
+    }
+
+    pub fn record_release(&mut self, arena: usize, bytes: usize) {
+        if let Some(lease) = self.leases.get_mut(&arena) {
+            lease.blocks -= 1;
+            lease.bytes -= bytes;
+            if lease.blocks == 0 {
+                self.leases.remove(&arena);
+            }
+        }
+    }
+}
+
+/// What to do when the last `Memory` handle is dropped while arenas still hold memory blocks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LeakCheck {
+    /// Do nothing.
+    Ignore,
+    /// Log the `LeakReport` as a warning with the `log` crate if the `logging` feature is enabled,
+    /// otherwise print it to the standard error.
+    Log,
+    /// Panic with the `LeakReport` as the message, unless the thread is already panicking.
+    Panic,
+}
+
+/// Arena that still holds memory blocks taken from `Memory`.
+#[derive(Debug, Clone)]
+pub struct LeasedArena {
+    /// Address of the arena.
+    pub arena: usize,
+    /// Number of blocks held by the arena.
+    pub blocks: usize,
+    /// Total size of blocks held by the arena.
+    pub bytes: usize,
+    /// `WeakArena` references that keep the arena blocks from being returned.
+    #[cfg(feature = "diagnostics")]
+    pub weak_holders: Vec<WeakHolder>,
+}
+
+/// Memory blocks that were not returned to `Memory`, returned by `Memory::leak_report`.
+#[derive(Debug, Clone, Default)]
+pub struct LeakReport {
+    /// Arenas that still hold blocks, ordered by address.
+    pub arenas: Vec<LeasedArena>,
+}
+
+impl LeakReport {
+    /// Returns true if all blocks were returned to `Memory`.
+    pub fn is_empty(&self) -> bool {
+        self.arenas.is_empty()
+    }
+
+    /// Returns the number of blocks that were not returned.
+    pub fn blocks(&self) -> usize {
+        self.arenas.iter().map(|a| a.blocks).sum()
+    }
+
+    /// Returns the total size of blocks that were not returned.
+    pub fn bytes(&self) -> usize {
+        self.arenas.iter().map(|a| a.bytes).sum()
+    }
+}
+
+impl Display for LeakReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} arenas hold {} blocks ({} bytes)", self.arenas.len(), self.blocks(), self.bytes())?;
+        for arena in &self.arenas {
+            write!(f, "\n  arena {:#x}: {} blocks ({} bytes)", arena.arena, arena.blocks, arena.bytes)?;
+            #[cfg(feature = "diagnostics")]
+            for holder in &arena.weak_holders {
+                write!(f, "\n    held by {}", holder)?;
+            }
+        }
+        Ok(())
+    }
 }
 
 /// Memory options builder.
@@ -62,6 +174,7 @@ pub struct MemoryBuilder {
     max_free_blocks_to_initialize_or_cleanup_to: i32,
     min_free_blocks_before_allocating_new: i32,
     new_block_size: usize,
+    leak_check: LeakCheck,
 }
 
 impl MemoryBuilder {
@@ -88,19 +201,110 @@ impl MemoryBuilder {
         self
     }
 
+    /// Specify what happens when the last `Memory` handle is dropped while arenas or their
+    /// weak references still hold memory blocks. The default is `LeakCheck::Ignore`.
+    pub fn with_leak_check(mut self, leak_check: LeakCheck) -> MemoryBuilder {
+        self.leak_check = leak_check;
+        self
+    }
+
     pub fn build(self) -> Memory {
         Memory {
-            shared: std::sync::Arc::new(std::sync::Mutex::new(ArenaMemoryInstance::new(&self)))
+            shared: std::sync::Arc::new(std::sync::Mutex::new(ArenaMemoryInstance::new(&self))),
+            teardowns: std::sync::Arc::new(PendingTeardowns::default()),
+            #[cfg(feature = "diagnostics")]
+            weak_holders: Default::default(),
+            user_handles: std::sync::Arc::new(AtomicUsize::new(1)),
+            is_user_handle: true,
         }
     }
 }
 
+/// Arenas that are being dropped in the background, executed one by one on a single worker thread.
+#[derive(Default)]
+struct PendingTeardowns {
+    state: std::sync::Mutex<TeardownQueue>,
+    done: std::sync::Condvar,
+    // leak check of the last `Memory` handle dropped by a teardown, executed after the teardowns
+    deferred_leak_check: std::sync::Mutex<Option<Memory>>,
+}
+
+thread_local! {
+    // true while the current thread is running arena teardowns
+    static IN_TEARDOWN: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+#[derive(Default)]
+struct TeardownQueue {
+    queue: VecDeque<Teardown>,
+    // number of queued and running teardowns
+    count: usize,
+    worker_running: bool,
+}
+
+impl PendingTeardowns {
+    /// Queues the teardown, and returns true if a worker should be started to run it.
+    fn push(&self, teardown: Teardown) -> bool {
+        let mut state = self.state.lock().expect("lock");
+        state.count += 1;
+        state.queue.push_back(teardown);
+        !std::mem::replace(&mut state.worker_running, true)
+    }
+
+    fn next(&self) -> Option<Teardown> {
+        let mut state = self.state.lock().expect("lock");
+        let teardown = state.queue.pop_front();
+        if teardown.is_none() {
+            state.worker_running = false;
+        }
+        teardown
+    }
+
+    /// Runs queued teardowns until the queue is empty, followed by the leak check that was
+    /// deferred while they were running.
+    fn run(&self) {
+        let in_teardown = IN_TEARDOWN.with(|flag| flag.replace(true));
+        while let Some(teardown) = self.next() {
+            let _finished = TeardownFinished(self);
+            if std::panic::catch_unwind(std::panic::AssertUnwindSafe(teardown)).is_err() {
+                warn!("-- arena teardown panicked, its memory is not returned");
+            }
+        }
+        IN_TEARDOWN.with(|flag| flag.set(in_teardown));
+
+        let deferred = self.deferred_leak_check.lock().expect("lock").take();
+        if let Some(memory) = deferred {
+            memory.check_leaks();
+        }
+    }
+}
+
+/// Marks the teardown as finished when dropped, even if the teardown panics.
+struct TeardownFinished<'a>(&'a PendingTeardowns);
+
+impl Drop for TeardownFinished<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.0.state.lock() {
+            state.count -= 1;
+        }
+        self.0.done.notify_all();
+    }
+}
+
+/// Arena drop work that is executed on a background thread.
+pub (crate) type Teardown = Box<dyn FnOnce() + Send>;
+
 /// Container of shared memory blocks.
 /// Does not automatically de-allocate memory!
 /// Call `cleanup` method to de-allocate when it is the most convenient.
-#[derive(Clone)]
 pub struct Memory {
     shared: std::sync::Arc<std::sync::Mutex<ArenaMemoryInstance>>,
+    teardowns: std::sync::Arc<PendingTeardowns>,
+    #[cfg(feature = "diagnostics")]
+    weak_holders: std::sync::Arc<std::sync::Mutex<WeakHolders>>,
+    // number of handles that are not owned by arenas
+    user_handles: std::sync::Arc<AtomicUsize>,
+    is_user_handle: bool,
 }
 
 impl Memory {
@@ -109,18 +313,23 @@ impl Memory {
             max_free_blocks_to_initialize_or_cleanup_to: 4,
             min_free_blocks_before_allocating_new: 2,
             new_block_size: 1024*64,
+            leak_check: LeakCheck::Ignore,
         }
     }
 
     pub fn new() -> Memory {
-        let builder = MemoryBuilder {
-            max_free_blocks_to_initialize_or_cleanup_to: 4,
-            min_free_blocks_before_allocating_new: 2,
-            new_block_size: 1024*64,
-        };
+        Memory::builder().build()
+    }
 
+    /// Returns a handle owned by an arena, which does not delay the leak check.
+    pub (crate) fn to_internal(&self) -> Memory {
         Memory {
-            shared: std::sync::Arc::new(std::sync::Mutex::new(ArenaMemoryInstance::new(&builder)))
+            shared: self.shared.clone(),
+            teardowns: self.teardowns.clone(),
+            #[cfg(feature = "diagnostics")]
+            weak_holders: self.weak_holders.clone(),
+            user_handles: self.user_handles.clone(),
+            is_user_handle: false,
         }
     }
 
@@ -140,4 +349,225 @@ impl Memory {
     pub fn return_block(&mut self, block: Box<[u8]>) {
         self.shared.lock().expect("lock").return_block(block)
     }
-}
\ No newline at end of file
+
+    /// Takes a block that has at least `len` bytes and records it as leased by the arena.
+    pub (crate) fn lease_block(&mut self, arena: usize, len: usize) -> Box<[u8]> {
+        let mut shared = self.shared.lock().expect("lock");
+        let block = shared.take_block_of_size(len);
+        shared.record_lease(arena, block.len());
+        block
+    }
+
+    /// Records a block that was taken by the arena without `lease_block`.
+    pub (crate) fn record_lease(&self, arena: usize, bytes: usize) {
+        self.shared.lock().expect("lock").record_lease(arena, bytes)
+    }
+
+    /// Returns the block leased by the arena.
+    pub (crate) fn release_block(&mut self, arena: usize, block: Box<[u8]>) {
+        let mut shared = self.shared.lock().expect("lock");
+        shared.record_release(arena, block.len());
+        shared.return_block(block);
+    }
+
+    /// Returns the arenas that still hold memory blocks, including arenas that were dropped
+    /// but are kept alive by `WeakArena` references, or are being dropped in the background.
+    pub fn leak_report(&self) -> LeakReport {
+        let leases: Vec<_> = self.shared.lock().expect("lock").leases.iter()
+            .map(|(arena, lease)| (*arena, lease.blocks, lease.bytes))
+            .collect();
+        LeakReport {
+            arenas: leases.into_iter()
+                .map(|(arena, blocks, bytes)| LeasedArena {
+                    arena,
+                    blocks,
+                    bytes,
+                    #[cfg(feature = "diagnostics")]
+                    weak_holders: self.weak_holders_of(arena),
+                })
+                .collect(),
+        }
+    }
+
+    fn check_leaks(&self) {
+        let leak_check = self.shared.lock().expect("lock").leak_check;
+        if leak_check == LeakCheck::Ignore {
+            return;
+        }
+        if IN_TEARDOWN.with(|flag| flag.get()) {
+            // waiting here would wait for the teardown that is running on this thread
+            *self.teardowns.deferred_leak_check.lock().expect("lock") = Some(self.to_internal());
+            return;
+        }
+        self.wait_for_teardowns();
+        let report = self.leak_report();
+        if report.is_empty() {
+            return;
+        }
+        if leak_check == LeakCheck::Panic && !std::thread::panicking() {
+            panic!("memory dropped with leased blocks: {}", report);
+        }
+        #[cfg(feature = "logging")]
+        warn!("memory dropped with leased blocks: {}", report);
+        #[cfg(not(feature = "logging"))]
+        eprintln!("memory dropped with leased blocks: {}", report);
+    }
+
+    /// Returns the number of arenas that are still being dropped on background threads.
+    pub fn pending_teardowns(&self) -> usize {
+        self.teardowns.state.lock().expect("lock").count
+    }
+
+    /// Blocks until all arenas dropped on background threads have returned their blocks.
+    pub fn wait_for_teardowns(&self) {
+        let mut state = self.teardowns.state.lock().expect("lock");
+        while state.count > 0 {
+            state = self.teardowns.done.wait(state).expect("lock");
+        }
+    }
+
+    /// Returns the `WeakArena` references of all arenas of this memory that are still alive,
+    /// together with the handle type and location where they were created.
+    #[cfg(feature = "diagnostics")]
+    pub fn weak_holders(&self) -> Vec<WeakHolder> {
+        self.weak_holders.lock().expect("lock").list(None)
+    }
+
+    #[cfg(feature = "diagnostics")]
+    pub (crate) fn weak_holders_of(&self, arena: usize) -> Vec<WeakHolder> {
+        self.weak_holders.lock().expect("lock").list(Some(arena))
+    }
+
+    #[cfg(feature = "diagnostics")]
+    pub (crate) fn register_weak_holder(&self, holder: WeakHolder) -> u64 {
+        self.weak_holders.lock().expect("lock").register(holder)
+    }
+
+    #[cfg(feature = "diagnostics")]
+    pub (crate) fn unregister_weak_holder(&self, id: u64) {
+        self.weak_holders.lock().expect("lock").unregister(id)
+    }
+
+    #[cfg(feature = "diagnostics")]
+    pub (crate) fn weak_holder_type(&self, id: u64) -> &'static str {
+        self.weak_holders.lock().expect("lock").handle_type(id)
+    }
+
+    /// Runs the teardown on the background worker thread, which is started when there is work and
+    /// exits when the queue is empty. Runs it on the current thread if the worker can not be spawned.
+    pub (crate) fn spawn_teardown(&self, teardown: Teardown) {
+        if !self.teardowns.push(teardown) {
+            return;
+        }
+
+        let teardowns = self.teardowns.clone();
+        let spawned = std::thread::Builder::new()
+            .name("memur-teardown".into())
+            .spawn(move || teardowns.run());
+
+        if spawned.is_err() {
+            debug!("-- failed to spawn teardown thread, dropping arena on current thread");
+            self.teardowns.run();
+        }
+    }
+}
+
+impl Clone for Memory {
+    fn clone(&self) -> Self {
+        if self.is_user_handle {
+            self.user_handles.fetch_add(1, Ordering::SeqCst);
+        }
+        Memory {
+            shared: self.shared.clone(),
+            teardowns: self.teardowns.clone(),
+            #[cfg(feature = "diagnostics")]
+            weak_holders: self.weak_holders.clone(),
+            user_handles: self.user_handles.clone(),
+            is_user_handle: self.is_user_handle,
+        }
+    }
+}
+
+impl Drop for Memory {
+    fn drop(&mut self) {
+        if self.is_user_handle && self.user_handles.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.check_leaks();
+        }
+    }
+}
+
+#[cfg(test)]
+mod memory_tests {
+    use crate::{Memory, Arena, UStr, N, LeakCheck};
+
+    #[test]
+    fn memory_dropped_by_background_teardown_does_not_wait_for_itself() {
+        use std::sync::mpsc::{channel, Sender};
+
+        struct Holder {
+            memory: Option<Memory>,
+            dropped: Sender<()>,
+        }
+
+        impl Drop for Holder {
+            fn drop(&mut self) {
+                drop(self.memory.take());
+                self.dropped.send(()).unwrap();
+            }
+        }
+
+        let (dropped, on_dropped) = channel();
+        let mem = Memory::builder().with_leak_check(LeakCheck::Log).build();
+        let arena = Arena::new(&mem).unwrap();
+        unsafe { arena.set_background_drop(true) };
+        N::new(&arena, Holder { memory: Some(mem.clone()), dropped }).unwrap();
+        drop(mem);
+        drop(arena);
+
+        on_dropped.recv_timeout(std::time::Duration::from_secs(10)).expect("last memory handle is dropped");
+    }
+
+    #[test]
+    fn leak_report_lists_arenas_holding_blocks() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let name = UStr::from_str(&arena, "name").unwrap();
+        let large = UStr::from_str(&arena, &"a".repeat(100_000)).unwrap();
+
+        let report = mem.leak_report();
+        assert_eq!(1, report.arenas.len());
+        assert_eq!(2, report.blocks(), "standard and dedicated block");
+        assert!(report.bytes() > 1024 * 64 + 100_000);
+        assert!(report.to_string().starts_with("1 arenas hold 2 blocks"));
+
+        drop(arena);
+        drop(large);
+        assert_eq!(2, mem.leak_report().blocks(), "weak reference holds the blocks");
+
+        drop(name);
+        assert!(mem.leak_report().is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "memory dropped with leased blocks")]
+    fn panics_when_last_memory_handle_is_dropped_with_leased_blocks() {
+        let mem = Memory::builder().with_leak_check(LeakCheck::Panic).build();
+        let mem_clone = mem.clone();
+        let arena = Arena::new(&mem).unwrap();
+        let name = UStr::from_str(&arena, "name").unwrap();
+        drop(arena);
+        drop(mem);
+        drop(mem_clone);
+        drop(name);
+    }
+
+    #[test]
+    fn does_not_panic_when_blocks_are_returned() {
+        let mem = Memory::builder().with_leak_check(LeakCheck::Panic).build();
+        let arena = Arena::new(&mem).unwrap();
+        let name = UStr::from_str(&arena, "name").unwrap();
+        drop(arena);
+        drop(name);
+        drop(mem);
+    }
+}
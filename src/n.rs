@@ -1,43 +1,308 @@
-use std::fmt::{Debug, Formatter};
-use crate::{WeakArena, Arena, UploadError, DropFn};
+use std::fmt::{Debug, Display, Formatter};
+use crate::{WeakArena, Arena, UploadError, DropPhase, Guard, GuardMut};
 use std::ptr::null_mut;
+use std::cell::Cell;
+use std::collections::HashSet;
 
-pub struct DropItem {
-    pub fun: DropFn,
-    pub data: *const u8,
-    pub next: *mut DropItem,
+/// Link to a value that must be dropped before the value that holds this link.
+struct NEdge {
+    header: *mut NHeader,
+    next: *mut NEdge,
 }
 
-impl DropItem {
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum NState {
+    Alive,
+    Dropping,
+    Dropped,
+}
+
+/// Part of the value metadata that does not depend on the value type, so that it can be
+/// used by handles that do not know the concrete type, such as `NDyn`.
+///
+/// It is placed at the start of `NMetadata`, so the pointer to the header is also
+/// the pointer to the metadata.
+struct NHeader {
+    // values that must be dropped before this value, newest first
+    dependents: *mut NEdge,
+    // number of shared borrows, or -1 if borrowed mutably
+    borrow: Cell<isize>,
+    // number of `N`, `NRef` or `NField` handles pointing to this value
+    handles: Cell<usize>,
+    state: Cell<NState>,
+    // drops the value of `NMetadata` located at the header pointer
+    drop_value: unsafe fn(*mut NHeader),
+}
+
+impl NHeader {
+    fn new<T>() -> NHeader {
+        NHeader {
+            dependents: null_mut(),
+            borrow: Cell::new(0),
+            handles: Cell::new(1),
+            state: Cell::new(NState::Alive),
+            drop_value: drop_value::<T>,
+        }
+    }
+
+    /// Drops all the dependents of this value and then the value itself, unless it was already dropped.
+    unsafe fn drop_node(header: *mut NHeader) {
+        if !NHeader::drop_dependents(header) {
+            return;
+        }
+        trace!("drop NMetadata");
+        ((*header).drop_value)(header);
+        (*header).state.set(NState::Dropped);
+    }
+
+    /// Marks the value as being dropped and drops all its dependents. Returns false if the value is
+    /// already dropped or being dropped. The caller must drop or move out the value and mark it as dropped.
+    unsafe fn drop_dependents(header: *mut NHeader) -> bool {
+        if (*header).state.get() != NState::Alive {
+            return false;
+        }
+        (*header).state.set(NState::Dropping);
+
+        let mut edge = (*header).dependents;
+        (*header).dependents = null_mut();
+        while !edge.is_null() {
+            trace!("drop dependent");
+            NHeader::drop_node((*edge).header);
+            edge = (*edge).next;
+        }
+        true
+    }
+
     #[inline(always)]
-    pub unsafe fn execute(&self) {
-        (self.fun)(self.data);
+    unsafe fn is_dropped(header: *const NHeader) -> bool {
+        (*header).state.get() != NState::Alive
+    }
+
+    /// Returns `BorrowError::AlreadyBorrowed` if the value or any value that must be dropped before it
+    /// is borrowed, so that the value can not be dropped or moved out under a live guard.
+    unsafe fn check_not_borrowed(header: *mut NHeader) -> Result<(), BorrowError> {
+        let mut visited = HashSet::new();
+        let mut stack = vec![header];
+        while let Some(header) = stack.pop() {
+            if !visited.insert(header) || NHeader::is_dropped(header) {
+                continue;
+            }
+            if (*header).borrow.get() != 0 {
+                return Err(BorrowError::AlreadyBorrowed);
+            }
+            let mut edge = (*header).dependents;
+            while !edge.is_null() {
+                stack.push((*edge).header);
+                edge = (*edge).next;
+            }
+        }
+        Ok(())
+    }
+
+    /// Drops the value before the arena is dropped.
+    unsafe fn drop_now(header: *mut NHeader, arena: &WeakArena) -> Result<(), BorrowError> {
+        let _arena = arena.arena().ok_or(BorrowError::ArenaIsNotAlive)?;
+        if NHeader::is_dropped(header) {
+            return Err(BorrowError::ValueIsDropped);
+        }
+        NHeader::check_not_borrowed(header)?;
+        if !NHeader::drop_dependents(header) {
+            return Err(BorrowError::ValueIsDropped);
+        }
+        trace!("drop NMetadata early");
+        ((*header).drop_value)(header);
+        (*header).state.set(NState::Dropped);
+        Ok(())
     }
+
+    /// Returns true if the `target` must be dropped before `from` is dropped.
+    unsafe fn reaches(from: *mut NHeader, target: *mut NHeader) -> bool {
+        let mut visited = HashSet::new();
+        let mut stack = vec![from];
+        while let Some(header) = stack.pop() {
+            if header == target {
+                return true;
+            }
+            if !visited.insert(header) {
+                continue;
+            }
+            let mut edge = (*header).dependents;
+            while !edge.is_null() {
+                stack.push((*edge).header);
+                edge = (*edge).next;
+            }
+        }
+        false
+    }
+
+    /// Links the `dependent` to this header, so that it is dropped before the value of this header.
+    unsafe fn push_dependent(header: *mut NHeader, arena: &Arena, dependent: *mut NHeader) -> Result<(), UploadError> {
+        let edge = arena.upload_no_drop(NEdge {
+            header: dependent,
+            next: (*header).dependents,
+        })?;
+        (*header).dependents = edge;
+        Ok(())
+    }
+
+    /// Puts the value to arena and links it to this header, so that it is
+    /// dropped before the value of this header.
+    unsafe fn push_outlives<O>(header: *mut NHeader, arena: &Arena, value: O) -> Result<*mut NMetadata<O>, UploadError> {
+        let o_wrapper_ptr = arena.upload_no_drop(NMetadata::new(value))?;
+        NHeader::push_dependent(header, arena, o_wrapper_ptr as *mut NHeader)?;
+        Ok(o_wrapper_ptr)
+    }
+
+    /// Ensures that the `dependent` is dropped before the value of this header.
+    unsafe fn must_outlive(header: *mut NHeader, arena: &WeakArena, dependent: DropNode) -> Result<(), DropOrderError> {
+        if !arena.is_same_arena(dependent.arena) {
+            return Err(DropOrderError::DifferentArenas);
+        }
+        let arena = arena.arena().ok_or(DropOrderError::ArenaIsNotAlive)?;
+        if NHeader::is_dropped(header) {
+            return Err(DropOrderError::ValueIsDropped);
+        }
+        if NHeader::reaches(dependent.header, header) {
+            return Err(DropOrderError::Cycle);
+        }
+        NHeader::push_dependent(header, &arena, dependent.header)?;
+        Ok(())
+    }
+
+    /// Borrows the value until the returned guard is dropped.
+    unsafe fn try_borrow<'a, V>(header: *const NHeader, arena: &WeakArena, value: *const V) -> Result<Guard<'a, V>, BorrowError> where V: ?Sized {
+        let arena = arena.arena().ok_or(BorrowError::ArenaIsNotAlive)?;
+        if NHeader::is_dropped(header) {
+            return Err(BorrowError::ValueIsDropped);
+        }
+        let borrow = &(*header).borrow;
+        if borrow.get() < 0 {
+            return Err(BorrowError::AlreadyMutablyBorrowed);
+        }
+        Ok(Guard::new_borrowed(arena, &*value, borrow))
+    }
+
+    /// Mutably borrows the value until the returned guard is dropped.
+    unsafe fn try_borrow_mut<'a, V>(header: *const NHeader, arena: &WeakArena, value: *mut V) -> Result<GuardMut<'a, V>, BorrowError> where V: ?Sized {
+        let arena = arena.arena().ok_or(BorrowError::ArenaIsNotAlive)?;
+        if NHeader::is_dropped(header) {
+            return Err(BorrowError::ValueIsDropped);
+        }
+        let borrow = &(*header).borrow;
+        if borrow.get() != 0 {
+            return Err(BorrowError::AlreadyBorrowed);
+        }
+        Ok(GuardMut::new_borrowed(arena, &mut *value, borrow))
+    }
+}
+
+/// Drop function placed in arena drop list for the values of `N`.
+unsafe fn drop_node(data: *const u8) {
+    NHeader::drop_node(data as *mut NHeader);
 }
 
+unsafe fn drop_value<T>(header: *mut NHeader) {
+    let metadata = header as *mut NMetadata<T>;
+    std::ptr::drop_in_place(std::ptr::addr_of_mut!((*metadata).value));
+}
+
+#[repr(C)]
 struct NMetadata<T> {
+    header: NHeader,
     value: T,
-    outlives: *mut DropItem,
 }
 
-impl<T> Drop for NMetadata<T> {
-    fn drop(&mut self) {
-        let mut outlives = self.outlives;
-        self.outlives = null_mut();
-        while outlives != null_mut() {
-            trace!("drop outlives");
-            unsafe {
-                (*outlives).execute();
-                let next = (*outlives).next;
-                (*outlives).next = null_mut();
-                outlives = next;
-            }
+impl<T> NMetadata<T> {
+    fn new(value: T) -> NMetadata<T> {
+        NMetadata { header: NHeader::new::<T>(), value }
+    }
+
+    /// Places the value to arena and registers the drop function in the drop list of the phase.
+    unsafe fn upload(arena: &Arena, phase: DropPhase, value: T) -> Result<*mut NMetadata<T>, UploadError> {
+        let item_ptr = arena.upload_no_drop(NMetadata::new(value))?;
+        arena.push_custom_drop_fn_in_phase(phase, drop_node, item_ptr as *const u8)?;
+        Ok(item_ptr)
+    }
+}
+
+/// Error while trying to declare the drop order of two values.
+#[derive(Debug)]
+pub enum DropOrderError {
+    /// The `Arena` was dropped together with the values.
+    ArenaIsNotAlive,
+    /// The values are stored in different arenas.
+    DifferentArenas,
+    /// The value already must be dropped before the other value, directly or over other values.
+    Cycle,
+    /// The value that should outlive the other value was already dropped.
+    ValueIsDropped,
+    /// Failed to place the drop order information to arena.
+    UploadError(UploadError),
+}
+
+impl Display for DropOrderError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DropOrderError::ArenaIsNotAlive => Display::fmt("Arena is not alive", f),
+            DropOrderError::DifferentArenas => Display::fmt("Values are stored in different arenas", f),
+            DropOrderError::Cycle => Display::fmt("Drop order contains a cycle", f),
+            DropOrderError::ValueIsDropped => Display::fmt("Value was already dropped", f),
+            DropOrderError::UploadError(e) => write!(f, "Failed to upload to arena - {}", e),
         }
-        trace!("drop NMetadata");
     }
 }
 
+impl From<UploadError> for DropOrderError {
+    fn from(other: UploadError) -> Self {
+        DropOrderError::UploadError(other)
+    }
+}
+
+impl std::error::Error for DropOrderError {}
+
+/// Reference to a value in arena drop order, used to declare that one value must outlive another.
+pub struct DropNode<'a> {
+    arena: &'a WeakArena,
+    header: *mut NHeader,
+}
+
+/// Handle to a value that takes part in arena drop order, implemented by `N`, `NRef` and `NDyn`.
+pub trait DropOrdered {
+    fn drop_node(&self) -> DropNode<'_>;
+}
+
+/// Error while trying to borrow the value of `N`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BorrowError {
+    /// The `Arena` was dropped together with the value.
+    ArenaIsNotAlive,
+    /// The value is already borrowed, and can not be borrowed mutably.
+    AlreadyBorrowed,
+    /// The value is already borrowed mutably, and can not be borrowed.
+    AlreadyMutablyBorrowed,
+    /// The value was dropped or moved out before the `Arena` was dropped.
+    ValueIsDropped,
+}
+
+impl Display for BorrowError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BorrowError::ArenaIsNotAlive => Display::fmt("Arena is not alive", f),
+            BorrowError::AlreadyBorrowed => Display::fmt("Value is already borrowed", f),
+            BorrowError::AlreadyMutablyBorrowed => Display::fmt("Value is already mutably borrowed", f),
+            BorrowError::ValueIsDropped => Display::fmt("Value was already dropped", f),
+        }
+    }
+}
+
+impl std::error::Error for BorrowError {}
+
 /// A wrapper of struct that is stored in arena memory.
+///
+/// The value has a borrow flag, similar to `RefCell`: it can be borrowed as shared by many guards
+/// returned from `borrow` or `get`, or as mutable by a single guard returned from `borrow_mut` or `get_mut`.
+/// The older accessors (`val`, `var`, `expect`, `expect_mut`) return the same guards, so that
+/// aliasing mutable access is detected instead of silently allowed.
 // can't clone because can be accessed as mutable
 pub struct N<T> {
     _arena: WeakArena,
@@ -46,78 +311,214 @@ pub struct N<T> {
 
 impl<T> N<T> {
     /// Stores the value in arena and returns a handle to it.
+    #[track_caller]
     pub fn new(arena: &Arena, value: T) -> Result<N<T>, UploadError> {
-        let wrapped = NMetadata { value, outlives: null_mut() };
-        let (item_ptr, _) = unsafe { arena.upload_auto_drop(wrapped)? };
+        let item_ptr = unsafe { NMetadata::upload(arena, DropPhase::DEFAULT, value)? };
         Ok(N {
-            _arena: arena.to_weak_arena(),
+            _arena: arena.to_weak_arena_for::<Self>(),
             _ptr: item_ptr,
         })
     }
 
-    /// Returns a reference to value or panics if arena is dead.
-    pub fn expect(&self, message: &str) -> (Arena, &T) {
-        if let Some(arena) = self._arena.arena() {
-            (arena, &unsafe { std::mem::transmute::<*mut NMetadata<T>, &NMetadata<T>>(self._ptr) }.value)
-        } else {
-            panic!("{}", message);
+    /// Stores the value in arena and returns a handle to it. The value is dropped together with
+    /// other values of the specified drop phase.
+    #[track_caller]
+    pub fn new_in_phase(arena: &Arena, phase: DropPhase, value: T) -> Result<N<T>, UploadError> {
+        let item_ptr = unsafe { NMetadata::upload(arena, phase, value)? };
+        Ok(N {
+            _arena: arena.to_weak_arena_for::<Self>(),
+            _ptr: item_ptr,
+        })
+    }
+
+    #[inline(always)]
+    fn header(&self) -> *mut NHeader {
+        unsafe { std::ptr::addr_of_mut!((*self._ptr).header) }
+    }
+
+    #[inline(always)]
+    fn value_ptr(&self) -> *mut T {
+        unsafe { std::ptr::addr_of_mut!((*self._ptr).value) }
+    }
+
+    /// Borrows the value until the returned guard is dropped, panics with the message if arena is dead,
+    /// the value is dropped, or the value is mutably borrowed.
+    pub fn expect(&self, message: &str) -> Guard<'_, T> {
+        match self.try_borrow() {
+            Ok(guard) => guard,
+            Err(e) => panic!("{}: {}", message, e),
         }
     }
 
-    /// Returns a reference to value if the arena is alive.
-    pub fn val(&self) -> Option<&T> {
-        if self._arena.is_alive() {
-            Some(&unsafe { std::mem::transmute::<*mut NMetadata<T>, &NMetadata<T>>(self._ptr) }.value)
-        } else {
-            None
+    /// Returns a guarded reference to value if the arena is alive, the value is not dropped, and the value is not mutably borrowed.
+    pub fn val(&self) -> Option<Guard<'_, T>> {
+        self.try_borrow().ok()
+    }
+
+    /// Returns a guarded reference to value if the arena is alive and the value is not mutably borrowed.
+    /// The guard keeps the arena alive until it is dropped.
+    pub fn get(&self) -> Option<Guard<'_, T>> {
+        self.try_borrow().ok()
+    }
+
+    /// Returns a guarded mutable reference to value if the arena is alive and the value is not borrowed.
+    /// The guard keeps the arena alive until it is dropped.
+    pub fn get_mut(&mut self) -> Option<GuardMut<'_, T>> {
+        self.try_borrow_mut().ok()
+    }
+
+    /// Borrows the value until the returned guard is dropped. The guard also keeps the arena alive.
+    pub fn try_borrow(&self) -> Result<Guard<'_, T>, BorrowError> {
+        unsafe { NHeader::try_borrow(self.header(), &self._arena, self.value_ptr()) }
+    }
+
+    /// Mutably borrows the value until the returned guard is dropped. The guard also keeps the arena alive.
+    pub fn try_borrow_mut(&self) -> Result<GuardMut<'_, T>, BorrowError> {
+        unsafe { NHeader::try_borrow_mut(self.header(), &self._arena, self.value_ptr()) }
+    }
+
+    /// Borrows the value until the returned guard is dropped, panics if the arena is dead or
+    /// the value is mutably borrowed.
+    pub fn borrow(&self) -> Guard<'_, T> {
+        match self.try_borrow() {
+            Ok(guard) => guard,
+            Err(e) => panic!("{}", e),
         }
     }
 
-    /// Returns a mutable reference to value or panics if arena is dead.
-    pub fn expect_mut(&self, message: &str) -> (Arena, &mut T) {
-        if let Some(arena) = self._arena.arena() {
-            (arena, &mut unsafe { std::mem::transmute::<*mut NMetadata<T>, &mut NMetadata<T>>(self._ptr) }.value)
-        } else {
-            panic!("{}", message);
+    /// Mutably borrows the value until the returned guard is dropped, panics if the arena is dead or
+    /// the value is borrowed.
+    pub fn borrow_mut(&self) -> GuardMut<'_, T> {
+        match self.try_borrow_mut() {
+            Ok(guard) => guard,
+            Err(e) => panic!("{}", e),
         }
     }
 
-    /// Returns a mutable reference to value if the arena is alive.
-    pub fn var(&mut self) -> Option<&mut T> {
-        if self._arena.is_alive() {
-            Some(&mut unsafe { std::mem::transmute::<*mut NMetadata<T>, &mut NMetadata<T>>(self._ptr) }.value)
-        } else {
-            None
+    /// Mutably borrows the value until the returned guard is dropped, panics with the message if arena is dead,
+    /// the value is dropped, or the value is borrowed.
+    pub fn expect_mut(&self, message: &str) -> GuardMut<'_, T> {
+        match self.try_borrow_mut() {
+            Ok(guard) => guard,
+            Err(e) => panic!("{}: {}", message, e),
+        }
+    }
+
+    /// Returns a guarded mutable reference to value if the arena is alive, the value is not dropped, and the value is not borrowed.
+    pub fn var(&mut self) -> Option<GuardMut<'_, T>> {
+        self.try_borrow_mut().ok()
+    }
+
+    /// Moves the value out of arena if the arena is alive and the value was not dropped yet.
+    ///
+    /// The drop function registered in arena is cancelled, and all values that this value must
+    /// outlive are dropped first. Other handles to this value, such as the handles returned
+    /// by `outlives`, report the value as dropped.
+    ///
+    /// Returns `BorrowError::AlreadyBorrowed` if this value or any of the values it must outlive
+    /// is borrowed by a guard.
+    pub fn into_inner(self) -> Result<T, BorrowError> {
+        let _arena = self._arena.arena().ok_or(BorrowError::ArenaIsNotAlive)?;
+        unsafe {
+            if NHeader::is_dropped(self.header()) {
+                return Err(BorrowError::ValueIsDropped);
+            }
+            NHeader::check_not_borrowed(self.header())?;
+            if !NHeader::drop_dependents(self.header()) {
+                return Err(BorrowError::ValueIsDropped);
+            }
+            let value = std::ptr::read(self.value_ptr());
+            (*self.header()).state.set(NState::Dropped);
+            Ok(value)
+        }
+    }
+
+    /// Drops the value in place before the arena is dropped, together with all values that this
+    /// value must outlive. Subsequent access to the value returns `None` or `BorrowError::ValueIsDropped`.
+    ///
+    /// Returns `BorrowError::AlreadyBorrowed` if this value or any of the values it must outlive
+    /// is borrowed by a guard.
+    pub fn drop_now(&mut self) -> Result<(), BorrowError> {
+        unsafe { NHeader::drop_now(self.header(), &self._arena) }
+    }
+
+    /// Returns a handle to a part of this value, i.e. a field of a struct.
+    ///
+    /// The returned handle shares the liveness check and the borrow flag of this value, so borrowing
+    /// the field marks the whole value as borrowed. The value is only borrowed mutably while the
+    /// `fun` is running.
+    ///
+    /// ```rust
+    /// use memur::{Memory, Arena, N};
+    ///
+    /// struct Renderer { frames: u32, name: String }
+    ///
+    /// let mem = Memory::new();
+    /// let arena = Arena::new(&mem).unwrap();
+    /// let renderer = N::new(&arena, Renderer { frames: 0, name: "main".into() }).unwrap();
+    /// let frames = renderer.map(|r| &mut r.frames).unwrap();
+    ///
+    /// *frames.borrow_mut() += 1;
+    /// assert_eq!(1, renderer.borrow().frames);
+    /// ```
+    #[track_caller]
+    pub fn map<U, F>(&self, fun: F) -> Result<NField<U>, BorrowError> where U: ?Sized, F: FnOnce(&mut T) -> &mut U {
+        unsafe { NField::new(self.header(), &self._arena, self.value_ptr(), fun) }
+    }
+
+    /// Returns a weak handle to this value that does not keep the value from being dropped early
+    /// or moved out with `into_inner`, and reports it as gone after that.
+    #[track_caller]
+    pub fn downgrade(&self) -> NWeak<T> {
+        NWeak {
+            _arena: self._arena.clone(),
+            _ptr: self._ptr,
+        }
+    }
+
+    /// Converts this handle into a shared read-only handle that can be cloned.
+    pub fn into_shared(self) -> NRef<T> {
+        NRef {
+            _arena: self._arena,
+            _ptr: self._ptr,
         }
     }
 
+    /// Converts this handle into a handle to unsized value, usually a trait object.
+    ///
+    /// The value is borrowed mutably while the `coerce` is running, so the conversion fails if
+    /// the arena is dead, the value is dropped, or the value is borrowed.
+    ///
+    /// ```rust
+    /// use memur::{Memory, Arena, N, NDyn};
+    /// use std::fmt::Display;
+    ///
+    /// let mem = Memory::new();
+    /// let arena = Arena::new(&mem).unwrap();
+    /// let value: NDyn<dyn Display> = N::new(&arena, 42).unwrap().into_dyn(|v| v as &mut dyn Display).unwrap();
+    /// assert_eq!("42", value.borrow().to_string());
+    /// ```
+    pub fn into_dyn<D, F>(self, coerce: F) -> Result<NDyn<D>, BorrowError> where D: ?Sized, F: FnOnce(&mut T) -> &mut D {
+        let ptr = {
+            let mut guard = unsafe { NHeader::try_borrow_mut(self.header(), &self._arena, self.value_ptr())? };
+            coerce(&mut *guard) as *mut D
+        };
+        Ok(NDyn {
+            _header: self.header(),
+            _ptr: ptr,
+            _arena: self._arena,
+        })
+    }
+
     /// Puts another `value` to the same `Arena` and ensures that it is dropped only after this
     /// value is dropped, in other words, this struct should outlive the specified struct.
     /// Super useful for managing deterministic drop order.
+    #[track_caller]
     pub fn outlives<O>(&self, value: O) -> Result<N<O>, UploadError> {
         match self._arena.arena() {
             None => Err(UploadError::ArenaIsNotAlive),
             Some(arena) => {
-                let wrapped = NMetadata { value, outlives: null_mut() };
-                let o_wrapper_ptr = unsafe { arena.upload_no_drop(wrapped)? };
-                let md = unsafe { std::mem::transmute::<*mut NMetadata<T>, &mut NMetadata<T>>(self._ptr) };
-                let drop_item = unsafe { arena.upload_no_drop(DropItem {
-                    fun: |data| {
-                        trace!("drop closure {:?}", data);
-                        let o_ref = std::mem::transmute::<*const u8, &NMetadata<O>>(data);
-                        let o = std::mem::transmute_copy::<NMetadata<O>, NMetadata<O>>(o_ref);
-                        std::mem::drop(o);
-                    },
-                    data: o_wrapper_ptr as *const u8,
-                    next: null_mut(),
-                }) }?;
-
-                if md.outlives == null_mut() {
-                    md.outlives = drop_item;
-                } else { unsafe {
-                    (*drop_item).next = md.outlives;
-                    md.outlives = drop_item;
-                } }
+                let o_wrapper_ptr = unsafe { NHeader::push_outlives(self.header(), &arena, value)? };
                 Ok(N {
                     _arena: self._arena.clone(),
                     _ptr: o_wrapper_ptr,
@@ -125,6 +526,21 @@ impl<T> N<T> {
             },
         }
     }
+
+    /// Ensures that the `other` value, which already exists in the same `Arena`, is dropped before this value.
+    ///
+    /// When this value is dropped, all values it must outlive are dropped first, so that the values
+    /// are dropped in topological order, regardless of the order they were created in.
+    /// Returns `DropOrderError::Cycle` if the `other` value must already outlive this value.
+    pub fn must_outlive(&self, other: &impl DropOrdered) -> Result<(), DropOrderError> {
+        unsafe { NHeader::must_outlive(self.header(), &self._arena, other.drop_node()) }
+    }
+}
+
+impl<T> DropOrdered for N<T> {
+    fn drop_node(&self) -> DropNode<'_> {
+        DropNode { arena: &self._arena, header: self.header() }
+    }
 }
 
 impl<T> Debug for N<T> where T: Debug {
@@ -139,6 +555,833 @@ impl<T> Debug for N<T> where T: Debug {
 
 impl<T> PartialEq for N<T> where T: PartialEq {
     fn eq(&self, other: &Self) -> bool {
-        self.val() == other.val()
+        match (self.val(), other.val()) {
+            (Some(a), Some(b)) => *a == *b,
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A shared read-only handle to the value stored in arena memory.
+///
+/// Unlike `N`, this handle can be cloned, and every clone holds its own `WeakArena`.
+/// The value can only be accessed over guards that keep the arena alive.
+pub struct NRef<T> {
+    _arena: WeakArena,
+    _ptr: *mut NMetadata<T>,
+}
+
+impl<T> NRef<T> {
+    /// Handle count, accessed without referencing the value.
+    #[inline(always)]
+    fn handles(&self) -> &Cell<usize> {
+        unsafe { &(*self._ptr).header.handles }
+    }
+
+    /// Returns the number of handles that point to this value.
+    pub fn handle_count(&self) -> usize {
+        self.handles().get()
+    }
+
+    /// Returns true if both handles point to the same value.
+    pub fn ptr_eq(&self, other: &NRef<T>) -> bool {
+        self._ptr == other._ptr
+    }
+
+    /// Returns a guarded reference to value if the arena is alive. The guard keeps the arena alive
+    /// until it is dropped.
+    pub fn get(&self) -> Option<Guard<'_, T>> {
+        self.try_borrow().ok()
+    }
+
+    /// Borrows the value until the returned guard is dropped. The guard also keeps the arena alive.
+    pub fn try_borrow(&self) -> Result<Guard<'_, T>, BorrowError> {
+        unsafe {
+            NHeader::try_borrow(
+                std::ptr::addr_of!((*self._ptr).header),
+                &self._arena,
+                std::ptr::addr_of!((*self._ptr).value)
+            )
+        }
+    }
+
+    /// Borrows the value until the returned guard is dropped, panics if the arena is dead.
+    pub fn borrow(&self) -> Guard<'_, T> {
+        match self.try_borrow() {
+            Ok(guard) => guard,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Converts back to the unique `N` handle if this is the only handle to the value,
+    /// otherwise returns this handle back.
+    pub fn try_into_unique(self) -> Result<N<T>, NRef<T>> {
+        if self.handle_count() != 1 {
+            return Err(self);
+        }
+        let this = std::mem::ManuallyDrop::new(self);
+        Ok(N {
+            _arena: unsafe { std::ptr::read(&this._arena) },
+            _ptr: this._ptr,
+        })
+    }
+}
+
+impl<T> DropOrdered for NRef<T> {
+    fn drop_node(&self) -> DropNode<'_> {
+        DropNode { arena: &self._arena, header: self._ptr as *mut NHeader }
+    }
+}
+
+impl<T> Clone for NRef<T> {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        self.handles().set(self.handles().get() + 1);
+        NRef {
+            _arena: self._arena.clone(),
+            _ptr: self._ptr,
+        }
+    }
+}
+
+impl<T> Drop for NRef<T> {
+    fn drop(&mut self) {
+        // memory is valid, because this handle still holds the weak arena
+        self.handles().set(self.handles().get() - 1);
+    }
+}
+
+impl<T> Debug for NRef<T> where T: Debug {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(v) = self.get() {
+            v.fmt(f)
+        } else {
+            write!(f, "dead")
+        }
+    }
+}
+
+impl<T> PartialEq for NRef<T> where T: PartialEq {
+    fn eq(&self, other: &Self) -> bool {
+        match (self.get(), other.get()) {
+            (Some(a), Some(b)) => *a == *b,
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+/// A handle to unsized value stored in arena memory, usually a trait object.
+///
+/// The concrete value is stored in arena together with its drop function, while this handle
+/// keeps the pointer to the value with the metadata needed to access it as `D`, i.e. the vtable.
+/// Like `N`, the value has a borrow flag, and supports `outlives` drop ordering.
+///
+/// ```rust
+/// use memur::{Memory, Arena, NDyn};
+///
+/// trait Plugin {
+///     fn name(&self) -> String;
+/// }
+///
+/// struct Hello;
+///
+/// impl Plugin for Hello {
+///     fn name(&self) -> String { "hello".into() }
+/// }
+///
+/// let mem = Memory::new();
+/// let arena = Arena::new(&mem).unwrap();
+/// let plugins = vec![
+///     NDyn::new(&arena, Hello, |v| v as &mut dyn Plugin).unwrap(),
+/// ];
+/// assert_eq!("hello", plugins[0].borrow().name());
+/// ```
+pub struct NDyn<D> where D: ?Sized {
+    _arena: WeakArena,
+    _header: *mut NHeader,
+    _ptr: *mut D,
+}
+
+impl<D> NDyn<D> where D: ?Sized {
+    /// Stores the value in arena and returns a handle to it as unsized `D`. The `coerce` function
+    /// should convert the reference to value into reference to `D`, i.e. `|v| v as &mut dyn Trait`.
+    #[track_caller]
+    pub fn new<T, F>(arena: &Arena, value: T, coerce: F) -> Result<NDyn<D>, UploadError> where F: FnOnce(&mut T) -> &mut D {
+        let value = N::new(arena, value)?;
+        Ok(value.into_dyn(coerce).expect("new value is alive and not borrowed"))
+    }
+
+    /// Returns a guarded reference to value if the arena is alive and the value is not mutably borrowed.
+    /// The guard keeps the arena alive until it is dropped.
+    pub fn get(&self) -> Option<Guard<'_, D>> {
+        self.try_borrow().ok()
+    }
+
+    /// Returns a guarded mutable reference to value if the arena is alive and the value is not borrowed.
+    /// The guard keeps the arena alive until it is dropped.
+    pub fn get_mut(&mut self) -> Option<GuardMut<'_, D>> {
+        self.try_borrow_mut().ok()
+    }
+
+    /// Borrows the value until the returned guard is dropped. The guard also keeps the arena alive.
+    pub fn try_borrow(&self) -> Result<Guard<'_, D>, BorrowError> {
+        unsafe { NHeader::try_borrow(self._header, &self._arena, self._ptr) }
     }
-}
\ No newline at end of file
+
+    /// Mutably borrows the value until the returned guard is dropped. The guard also keeps the arena alive.
+    pub fn try_borrow_mut(&self) -> Result<GuardMut<'_, D>, BorrowError> {
+        unsafe { NHeader::try_borrow_mut(self._header, &self._arena, self._ptr) }
+    }
+
+    /// Borrows the value until the returned guard is dropped, panics if the arena is dead or
+    /// the value is mutably borrowed.
+    pub fn borrow(&self) -> Guard<'_, D> {
+        match self.try_borrow() {
+            Ok(guard) => guard,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Mutably borrows the value until the returned guard is dropped, panics if the arena is dead or
+    /// the value is borrowed.
+    pub fn borrow_mut(&self) -> GuardMut<'_, D> {
+        match self.try_borrow_mut() {
+            Ok(guard) => guard,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Puts another `value` to the same `Arena` and ensures that it is dropped only after this
+    /// value is dropped, same as `N::outlives`.
+    #[track_caller]
+    pub fn outlives<O>(&self, value: O) -> Result<N<O>, UploadError> {
+        match self._arena.arena() {
+            None => Err(UploadError::ArenaIsNotAlive),
+            Some(arena) => {
+                let o_wrapper_ptr = unsafe { NHeader::push_outlives(self._header, &arena, value)? };
+                Ok(N {
+                    _arena: self._arena.clone(),
+                    _ptr: o_wrapper_ptr,
+                })
+            },
+        }
+    }
+
+    /// Drops the value in place before the arena is dropped, same as `N::drop_now`.
+    pub fn drop_now(&mut self) -> Result<(), BorrowError> {
+        unsafe { NHeader::drop_now(self._header, &self._arena) }
+    }
+
+    /// Ensures that the `other` value is dropped before this value, same as `N::must_outlive`.
+    pub fn must_outlive(&self, other: &impl DropOrdered) -> Result<(), DropOrderError> {
+        unsafe { NHeader::must_outlive(self._header, &self._arena, other.drop_node()) }
+    }
+}
+
+impl<D> DropOrdered for NDyn<D> where D: ?Sized {
+    fn drop_node(&self) -> DropNode<'_> {
+        DropNode { arena: &self._arena, header: self._header }
+    }
+}
+
+impl<D> Debug for NDyn<D> where D: Debug + ?Sized {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(v) = self.get() {
+            v.fmt(f)
+        } else {
+            write!(f, "dead")
+        }
+    }
+}
+
+/// A handle to a part of value stored in arena, i.e. a field of a struct, returned by `N::map`.
+///
+/// It shares the borrow flag with the whole value, and returns nothing after the arena is dead or
+/// the value is dropped.
+pub struct NField<U> where U: ?Sized {
+    _arena: WeakArena,
+    _header: *mut NHeader,
+    _ptr: *mut U,
+}
+
+impl<U> NField<U> where U: ?Sized {
+    #[track_caller]
+    unsafe fn new<T, F>(header: *mut NHeader, arena: &WeakArena, value: *mut T, fun: F) -> Result<NField<U>, BorrowError> where T: ?Sized, F: FnOnce(&mut T) -> &mut U {
+        let mut guard = NHeader::try_borrow_mut(header, arena, value)?;
+        let ptr = fun(&mut *guard) as *mut U;
+        (*header).handles.set((*header).handles.get() + 1);
+        Ok(NField {
+            _arena: arena.clone(),
+            _header: header,
+            _ptr: ptr,
+        })
+    }
+
+    /// Returns a handle to a part of this field.
+    #[track_caller]
+    pub fn map<V, F>(&self, fun: F) -> Result<NField<V>, BorrowError> where V: ?Sized, F: FnOnce(&mut U) -> &mut V {
+        unsafe { NField::new(self._header, &self._arena, self._ptr, fun) }
+    }
+
+    /// Returns a guarded reference to field if the arena is alive and the value is not mutably borrowed.
+    /// The guard keeps the arena alive until it is dropped.
+    pub fn get(&self) -> Option<Guard<'_, U>> {
+        self.try_borrow().ok()
+    }
+
+    /// Returns a guarded mutable reference to field if the arena is alive and the value is not borrowed.
+    /// The guard keeps the arena alive until it is dropped.
+    pub fn get_mut(&mut self) -> Option<GuardMut<'_, U>> {
+        self.try_borrow_mut().ok()
+    }
+
+    /// Borrows the value until the returned guard is dropped. The guard also keeps the arena alive.
+    pub fn try_borrow(&self) -> Result<Guard<'_, U>, BorrowError> {
+        unsafe { NHeader::try_borrow(self._header, &self._arena, self._ptr) }
+    }
+
+    /// Mutably borrows the value until the returned guard is dropped. The guard also keeps the arena alive.
+    pub fn try_borrow_mut(&self) -> Result<GuardMut<'_, U>, BorrowError> {
+        unsafe { NHeader::try_borrow_mut(self._header, &self._arena, self._ptr) }
+    }
+
+    /// Borrows the value until the returned guard is dropped, panics if the arena is dead or
+    /// the value is mutably borrowed.
+    pub fn borrow(&self) -> Guard<'_, U> {
+        match self.try_borrow() {
+            Ok(guard) => guard,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Mutably borrows the value until the returned guard is dropped, panics if the arena is dead or
+    /// the value is borrowed.
+    pub fn borrow_mut(&self) -> GuardMut<'_, U> {
+        match self.try_borrow_mut() {
+            Ok(guard) => guard,
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+impl<U> Drop for NField<U> where U: ?Sized {
+    fn drop(&mut self) {
+        // memory is valid, because this handle still holds the weak arena
+        unsafe { (*self._header).handles.set((*self._header).handles.get() - 1) };
+    }
+}
+
+impl<U> Debug for NField<U> where U: Debug + ?Sized {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(v) = self.get() {
+            v.fmt(f)
+        } else {
+            write!(f, "dead")
+        }
+    }
+}
+
+/// A weak handle to value stored in arena, returned by `N::downgrade`.
+///
+/// It gives only shared access to the value, and returns nothing after the arena is dead,
+/// or the value was dropped early or moved out of arena.
+///
+/// Unlike `WeakItem` of `Array`, it does not need a generation stamp: the memory of a value
+/// is never reused for another value while the arena is alive, so the drop state of the value is enough.
+pub struct NWeak<T> {
+    _arena: WeakArena,
+    _ptr: *mut NMetadata<T>,
+}
+
+impl<T> NWeak<T> {
+    #[inline(always)]
+    fn header(&self) -> *mut NHeader {
+        unsafe { std::ptr::addr_of_mut!((*self._ptr).header) }
+    }
+
+    /// Returns true if the arena is alive and the value was not dropped or moved out.
+    pub fn is_alive(&self) -> bool {
+        // memory is valid, because this handle still holds the weak arena
+        self._arena.is_alive() && !unsafe { NHeader::is_dropped(self.header()) }
+    }
+
+    /// Returns a guarded reference to value if it is alive and not mutably borrowed.
+    /// The guard keeps the arena alive until it is dropped.
+    pub fn get(&self) -> Option<Guard<'_, T>> {
+        self.try_borrow().ok()
+    }
+
+    /// Borrows the value until the returned guard is dropped. The guard also keeps the arena alive.
+    pub fn try_borrow(&self) -> Result<Guard<'_, T>, BorrowError> {
+        unsafe { NHeader::try_borrow(self.header(), &self._arena, std::ptr::addr_of!((*self._ptr).value)) }
+    }
+}
+
+impl<T> Clone for NWeak<T> {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        NWeak {
+            _arena: self._arena.clone(),
+            _ptr: self._ptr,
+        }
+    }
+}
+
+impl<T> Debug for NWeak<T> where T: Debug {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        if let Some(v) = self.get() {
+            v.fmt(f)
+        } else {
+            write!(f, "dead")
+        }
+    }
+}
+
+#[cfg(test)]
+mod n_tests {
+    use crate::{Memory, Arena, N, NDyn, BorrowError, DropOrderError};
+    use crate::dropflag::{Droppable, DropFlag};
+    use std::cell::RefCell;
+
+    #[test]
+    fn shared_borrows_prevent_mutable_borrow() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut obj = N::new(&arena, 5).unwrap();
+
+        {
+            let a = obj.borrow();
+            let b = obj.borrow();
+            assert_eq!(10, *a + *b);
+            assert_eq!(BorrowError::AlreadyBorrowed, obj.try_borrow_mut().err().unwrap());
+            assert_eq!(5, *obj.val().unwrap());
+        }
+
+        *obj.borrow_mut() = 6;
+        assert_eq!(6, *obj.var().unwrap());
+    }
+
+    #[test]
+    fn mutable_borrow_prevents_other_access() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let obj = N::new(&arena, 5).unwrap();
+
+        {
+            let mut guard = obj.borrow_mut();
+            *guard = 7;
+            assert_eq!(BorrowError::AlreadyMutablyBorrowed, obj.try_borrow().err().unwrap());
+            assert_eq!(BorrowError::AlreadyBorrowed, obj.try_borrow_mut().err().unwrap());
+            assert!(obj.val().is_none());
+        }
+
+        assert_eq!(7, *obj.borrow());
+    }
+
+    #[test]
+    #[should_panic]
+    fn aliasing_mutable_access_panics() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let obj = N::new(&arena, 5).unwrap();
+
+        let _guard = obj.borrow_mut();
+        let _alias = obj.expect_mut("value is borrowed");
+    }
+
+    #[test]
+    #[should_panic]
+    fn repeated_expect_mut_panics() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let obj = N::new(&arena, 5).unwrap();
+
+        let _first = obj.expect_mut("value is not borrowed");
+        let _alias = obj.expect_mut("value is borrowed");
+    }
+
+    #[test]
+    fn expect_accessors_hold_the_borrow_flag() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut obj = N::new(&arena, 5).unwrap();
+
+        {
+            let mut value = obj.expect_mut("value is not borrowed");
+            *value = 6;
+            assert_eq!(BorrowError::AlreadyMutablyBorrowed, obj.try_borrow().err().unwrap());
+            assert_eq!(BorrowError::AlreadyBorrowed, obj.try_borrow_mut().err().unwrap());
+            assert!(obj.val().is_none());
+        }
+
+        {
+            let value = obj.expect("value is not mutably borrowed");
+            let other = obj.val().unwrap();
+            assert_eq!(12, *value + *other);
+            assert_eq!(BorrowError::AlreadyBorrowed, obj.try_borrow_mut().err().unwrap());
+        }
+
+        let field = obj.map(|v| v).unwrap();
+        {
+            let _guard = field.borrow();
+            assert!(obj.var().is_none());
+        }
+        *obj.var().unwrap() = 7;
+        assert_eq!(7, *field.borrow());
+    }
+
+    #[test]
+    fn borrow_fails_when_arena_is_dead() {
+        let mem = Memory::new();
+        let obj = {
+            let arena = Arena::new(&mem).unwrap();
+            N::new(&arena, 5).unwrap()
+        };
+
+        assert_eq!(BorrowError::ArenaIsNotAlive, obj.try_borrow().err().unwrap());
+        assert_eq!(BorrowError::ArenaIsNotAlive, obj.try_borrow_mut().err().unwrap());
+    }
+
+    #[test]
+    fn shared_handles_read_the_same_value() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let shared = N::new(&arena, 5).unwrap().into_shared();
+        let other = shared.clone();
+
+        assert!(shared.ptr_eq(&other));
+        assert_eq!(2, shared.handle_count());
+        assert_eq!(10, *shared.borrow() + *other.borrow());
+
+        let shared = shared.try_into_unique().unwrap_err();
+        drop(other);
+        let mut unique = shared.try_into_unique().unwrap();
+        *unique.var().unwrap() = 6;
+        assert_eq!(6, *unique.val().unwrap());
+    }
+
+    #[test]
+    fn shared_handles_do_not_keep_value_alive() {
+        let flag = DropFlag::new(RefCell::new(false));
+        let mem = Memory::new();
+        let shared = {
+            let arena = Arena::new(&mem).unwrap();
+            N::new(&arena, Droppable { dropflag: flag.clone() }).unwrap().into_shared()
+        };
+        let other = shared.clone();
+
+        assert!(*flag.borrow(), "value was dropped with arena");
+        assert!(shared.get().is_none());
+        assert!(other.get().is_none());
+    }
+
+    trait Counter {
+        fn increment(&mut self);
+        fn count(&self) -> i32;
+    }
+
+    struct DroppableCounter {
+        count: i32,
+        log: DropFlag<Vec<&'static str>>,
+    }
+
+    impl Counter for DroppableCounter {
+        fn increment(&mut self) {
+            self.count += 1;
+        }
+
+        fn count(&self) -> i32 {
+            self.count
+        }
+    }
+
+    impl Drop for DroppableCounter {
+        fn drop(&mut self) {
+            self.log.borrow_mut().push("counter");
+        }
+    }
+
+    struct Logged(&'static str, DropFlag<Vec<&'static str>>);
+
+    impl Drop for Logged {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    #[test]
+    fn trait_objects_are_accessed_and_dropped() {
+        let log = DropFlag::new(RefCell::new(Vec::new()));
+        let mem = Memory::new();
+        {
+            let arena = Arena::new(&mem).unwrap();
+            let mut items: Vec<NDyn<dyn Counter>> = vec![
+                NDyn::new(&arena, DroppableCounter { count: 0, log: log.clone() }, |v| v as &mut dyn Counter).unwrap(),
+                N::new(&arena, DroppableCounter { count: 10, log: log.clone() }).unwrap().into_dyn(|v| v as &mut dyn Counter).unwrap(),
+            ];
+
+            for item in items.iter_mut() {
+                item.get_mut().unwrap().increment();
+            }
+            assert_eq!(1, items[0].borrow().count());
+            assert_eq!(11, items[1].borrow().count());
+
+            let _guard = items[0].borrow();
+            assert_eq!(BorrowError::AlreadyBorrowed, items[0].try_borrow_mut().err().unwrap());
+
+            let _dependent = items[0].outlives(Logged("dependent", log.clone())).unwrap();
+        }
+
+        assert_eq!(vec!["dependent", "counter", "counter"], *log.borrow());
+    }
+
+    #[test]
+    fn declared_drop_order_is_respected_regardless_of_creation_order() {
+        let log = DropFlag::new(RefCell::new(Vec::new()));
+        let mem = Memory::new();
+        {
+            let arena = Arena::new(&mem).unwrap();
+            let framebuffer = N::new(&arena, Logged("framebuffer", log.clone())).unwrap();
+            let device = N::new(&arena, Logged("device", log.clone())).unwrap();
+            let swapchain = N::new(&arena, Logged("swapchain", log.clone())).unwrap();
+
+            device.must_outlive(&swapchain).unwrap();
+            swapchain.must_outlive(&framebuffer).unwrap();
+            device.must_outlive(&framebuffer).unwrap();
+        }
+
+        assert_eq!(vec!["framebuffer", "swapchain", "device"], *log.borrow());
+    }
+
+    #[test]
+    fn drop_order_cycles_are_detected() {
+        let log = DropFlag::new(RefCell::new(Vec::new()));
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let a = N::new(&arena, Logged("a", log.clone())).unwrap();
+        let b = N::new(&arena, Logged("b", log.clone())).unwrap();
+        let c = a.outlives(Logged("c", log.clone())).unwrap();
+
+        b.must_outlive(&a).unwrap();
+        assert!(matches!(a.must_outlive(&b), Err(DropOrderError::Cycle)));
+        assert!(matches!(c.must_outlive(&b), Err(DropOrderError::Cycle)));
+        assert!(matches!(a.must_outlive(&a), Err(DropOrderError::Cycle)));
+
+        let other_arena = Arena::new(&mem).unwrap();
+        let d = N::new(&other_arena, Logged("d", log.clone())).unwrap();
+        assert!(matches!(a.must_outlive(&d), Err(DropOrderError::DifferentArenas)));
+
+        drop(arena);
+        assert_eq!(vec!["c", "a", "b"], *log.borrow());
+    }
+
+    #[test]
+    fn value_can_be_moved_out_after_dropping_dependents() {
+        let log = DropFlag::new(RefCell::new(Vec::new()));
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let a = N::new(&arena, Logged("a", log.clone())).unwrap();
+        let b = a.outlives(Logged("b", log.clone())).unwrap();
+        let c = N::new(&arena, Logged("c", log.clone())).unwrap();
+        a.must_outlive(&c).unwrap();
+
+        let value = a.into_inner().unwrap();
+        assert_eq!(vec!["c", "b"], *log.borrow());
+        assert!(b.val().is_none());
+        assert_eq!(BorrowError::ValueIsDropped, c.try_borrow().err().unwrap());
+
+        drop(arena);
+        assert_eq!(vec!["c", "b"], *log.borrow(), "values are not dropped twice");
+        drop(value);
+        assert_eq!(vec!["c", "b", "a"], *log.borrow());
+    }
+
+    #[test]
+    fn value_can_be_dropped_early() {
+        let log = DropFlag::new(RefCell::new(Vec::new()));
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut a = N::new(&arena, Logged("a", log.clone())).unwrap();
+        let mut d = NDyn::new(&arena, 5, |v| v as &mut dyn std::fmt::Debug).unwrap();
+        let _b = a.outlives(Logged("b", log.clone())).unwrap();
+
+        a.drop_now().unwrap();
+        assert_eq!(vec!["b", "a"], *log.borrow());
+        assert!(a.val().is_none());
+        assert!(a.var().is_none());
+        assert!(a.get().is_none());
+        assert_eq!(BorrowError::ValueIsDropped, a.drop_now().err().unwrap());
+        assert!(matches!(a.must_outlive(&d), Err(DropOrderError::ValueIsDropped)));
+
+        d.drop_now().unwrap();
+        assert!(d.get().is_none());
+
+        drop(arena);
+        assert_eq!(vec!["b", "a"], *log.borrow(), "values are not dropped twice");
+    }
+
+    #[test]
+    fn field_handles_share_borrow_flag() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let pair = N::new(&arena, (1, (2, 3))).unwrap();
+        let first = pair.map(|p| &mut p.0).unwrap();
+        let inner = pair.map(|p| &mut p.1).unwrap().map(|p| &mut p.1).unwrap();
+
+        *first.borrow_mut() = 5;
+        *inner.borrow_mut() = 7;
+        assert_eq!((5, (2, 7)), *pair.borrow());
+
+        let guard = first.borrow_mut();
+        assert_eq!(BorrowError::AlreadyMutablyBorrowed, pair.try_borrow().err().unwrap());
+        assert_eq!(BorrowError::AlreadyMutablyBorrowed, inner.try_borrow().err().unwrap());
+        assert!(pair.map(|p| &mut p.0).is_err());
+        drop(guard);
+
+        let shared = pair.into_shared();
+        assert_eq!(3, shared.handle_count());
+        drop(first);
+        drop(inner);
+        assert!(shared.try_into_unique().is_ok());
+    }
+
+    #[test]
+    fn field_handles_are_dead_after_value_is_dropped() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut pair = N::new(&arena, (1, 2)).unwrap();
+        let second = pair.map(|p| &mut p.1).unwrap();
+        assert_eq!(2, *second.borrow());
+
+        pair.drop_now().unwrap();
+        assert_eq!(BorrowError::ValueIsDropped, second.try_borrow().err().unwrap());
+
+        let pair = N::new(&arena, (1, 2)).unwrap();
+        let second = pair.map(|p| &mut p.1).unwrap();
+        drop(arena);
+        assert_eq!(BorrowError::ArenaIsNotAlive, second.try_borrow().err().unwrap());
+    }
+
+    #[test]
+    fn weak_handles_detect_early_dropped_values() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut a = N::new(&arena, 1).unwrap();
+        let b = N::new(&arena, 2).unwrap();
+        let weak_a = a.downgrade();
+        let weak_b = b.downgrade();
+
+        assert_eq!(1, *weak_a.get().unwrap());
+        *b.borrow_mut() = 3;
+        assert_eq!(3, *weak_b.get().unwrap());
+        {
+            let _guard = weak_b.get().unwrap();
+            assert_eq!(BorrowError::AlreadyBorrowed, b.try_borrow_mut().err().unwrap());
+        }
+
+        a.drop_now().unwrap();
+        assert!(!weak_a.is_alive());
+        assert_eq!(BorrowError::ValueIsDropped, weak_a.try_borrow().err().unwrap());
+
+        assert_eq!(Ok(3), b.into_inner());
+        assert!(weak_b.get().is_none());
+
+        let c = N::new(&arena, 4).unwrap();
+        let weak_c = c.downgrade();
+        drop(arena);
+        assert_eq!(BorrowError::ArenaIsNotAlive, weak_c.try_borrow().err().unwrap());
+    }
+
+    #[test]
+    fn borrowed_value_is_not_moved_out_or_dropped() {
+        let log = DropFlag::new(RefCell::new(Vec::new()));
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut a = N::new(&arena, Logged("a", log.clone())).unwrap();
+
+        let weak = a.downgrade();
+        {
+            let _guard = weak.get().unwrap();
+            assert_eq!(BorrowError::AlreadyBorrowed, a.drop_now().err().unwrap());
+        }
+        let field = a.map(|v| &mut v.0).unwrap();
+        {
+            let guard = field.borrow();
+            assert_eq!(BorrowError::AlreadyBorrowed, a.into_inner().err().unwrap());
+            assert_eq!("a", *guard);
+        }
+        assert!(log.borrow().is_empty(), "value is not dropped while borrowed");
+        assert_eq!("a", *field.borrow());
+
+        drop(arena);
+        assert_eq!(vec!["a"], *log.borrow());
+    }
+
+    #[test]
+    fn value_is_not_dropped_while_dependent_is_borrowed() {
+        let log = DropFlag::new(RefCell::new(Vec::new()));
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut a = N::new(&arena, Logged("a", log.clone())).unwrap();
+        let b = a.outlives(Logged("b", log.clone())).unwrap();
+        let c = b.outlives(Logged("c", log.clone())).unwrap();
+
+        {
+            let guard = c.borrow();
+            assert_eq!(BorrowError::AlreadyBorrowed, a.drop_now().err().unwrap());
+            assert_eq!("c", guard.0);
+        }
+        assert!(log.borrow().is_empty(), "values are not dropped while borrowed");
+
+        a.drop_now().unwrap();
+        assert_eq!(vec!["c", "b", "a"], *log.borrow());
+    }
+
+    #[test]
+    fn declared_dependent_borrow_prevents_early_drop() {
+        let log = DropFlag::new(RefCell::new(Vec::new()));
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut device = NDyn::new(&arena, Logged("device", log.clone()), |v| v as &mut dyn std::any::Any).unwrap();
+        let swapchain = N::new(&arena, Logged("swapchain", log.clone())).unwrap();
+        let framebuffer = N::new(&arena, Logged("framebuffer", log.clone())).unwrap();
+        device.must_outlive(&swapchain).unwrap();
+        swapchain.must_outlive(&framebuffer).unwrap();
+
+        {
+            let _guard = framebuffer.borrow_mut();
+            assert_eq!(BorrowError::AlreadyBorrowed, device.drop_now().err().unwrap());
+            assert_eq!(BorrowError::AlreadyBorrowed, swapchain.into_inner().err().unwrap());
+        }
+        assert!(log.borrow().is_empty(), "values are not dropped while borrowed");
+
+        device.drop_now().unwrap();
+        assert_eq!(vec!["framebuffer", "swapchain", "device"], *log.borrow());
+    }
+
+    #[test]
+    fn into_dyn_fails_when_value_is_not_accessible() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+
+        let mut dropped = N::new(&arena, 1).unwrap();
+        dropped.drop_now().unwrap();
+        assert_eq!(BorrowError::ValueIsDropped, dropped.into_dyn(|v| v as &mut dyn std::fmt::Debug).err().unwrap());
+
+        let borrowed = N::new(&arena, 2).unwrap();
+        let weak = borrowed.downgrade();
+        let guard = weak.get().unwrap();
+        assert_eq!(BorrowError::AlreadyBorrowed, borrowed.into_dyn(|v| v as &mut dyn std::fmt::Debug).err().unwrap());
+        assert_eq!(2, *guard);
+        drop(guard);
+
+        let converted = N::new(&arena, 3).unwrap().into_dyn(|v| v as &mut dyn std::fmt::Debug).unwrap();
+        assert_eq!("3", format!("{:?}", converted));
+
+        let orphan = N::new(&arena, 4).unwrap();
+        drop(arena);
+        assert_eq!(BorrowError::ArenaIsNotAlive, orphan.into_dyn(|v| v as &mut dyn std::fmt::Debug).err().unwrap());
+    }
+}
@@ -0,0 +1,325 @@
+use crate::{Arena, UploadError, WeakArena, Guard, GuardMut};
+use std::ops::{Index, IndexMut};
+use std::ptr::null_mut;
+
+/// Maximum number of chunks, enough to address every `usize` index.
+const MAX_CHUNKS: usize = usize::BITS as usize;
+
+/// Arena‐uploaded metadata for the segmented vector. The chunk `k` holds `2^k` items,
+/// and is null if it was not allocated yet.
+pub(crate) struct SegmentedVecMetadata<T> {
+    pub _len: usize,
+    pub _chunk_count: usize,
+    pub _chunks: [*mut T; MAX_CHUNKS],
+}
+
+/// Arena‐uploaded data of the drop function of a single chunk.
+struct ChunkDrop<T> {
+    metadata: *mut SegmentedVecMetadata<T>,
+    chunk: usize,
+}
+
+/// Returns the chunk that contains the item at `index`, and the item offset in this chunk.
+#[inline(always)]
+fn chunk_and_offset(index: usize) -> (usize, usize) {
+    // items before chunk k: 2^k - 1
+    let chunk = (usize::BITS - 1 - (index + 1).leading_zeros()) as usize;
+    (chunk, index + 1 - (1 << chunk))
+}
+
+/// Returns the number of items in the first `chunk_count` chunks.
+#[inline(always)]
+fn items_before_chunk(chunk_count: usize) -> usize {
+    if chunk_count >= MAX_CHUNKS {
+        usize::MAX
+    } else {
+        (1 << chunk_count) - 1
+    }
+}
+
+/// Custom drop function for a single chunk, drops the items of the chunk that are still in the vector.
+fn drop_chunk<T>(data: *const u8) {
+    unsafe {
+        let chunk_drop = &*(data as *const ChunkDrop<T>);
+        let meta = &*chunk_drop.metadata;
+        let first = items_before_chunk(chunk_drop.chunk);
+        let count = meta._len.saturating_sub(first).min(1 << chunk_drop.chunk);
+        let items = std::ptr::slice_from_raw_parts_mut(meta._chunks[chunk_drop.chunk], count);
+        std::ptr::drop_in_place(items);
+    }
+}
+
+/// A growable arena–backed vector that stores items in chunks of growing size.
+///
+/// The chunk `k` holds `2^k` items, so the item is found by its index with a few bit operations.
+/// The chunks are never moved, so the item addresses are stable for as long as the item is in
+/// the vector, and the items of every chunk are contiguous. Every chunk registers one drop
+/// function that drops the chunk items when the arena is dropped.
+///
+/// ```rust
+/// use memur::{Memory, Arena, SegmentedVec};
+///
+/// let mem = Memory::new();
+/// let arena = Arena::new(&mem).unwrap();
+/// let mut items = SegmentedVec::new(&arena).unwrap();
+/// items.push(1).unwrap();
+/// let first = &items[0] as *const i32;
+/// for i in 2..100 {
+///     items.push(i).unwrap();
+/// }
+/// assert_eq!(first, &items[0] as *const i32);
+/// assert_eq!(Some(&50), items.get_at(49));
+/// assert_eq!(vec![&[1][..], &[2, 3][..]], items.chunks().take(2).collect::<Vec<_>>());
+/// ```
+pub struct SegmentedVec<T> where T: Sized {
+    _arena: WeakArena,
+    _metadata: *mut SegmentedVecMetadata<T>,
+}
+
+impl<T> SegmentedVec<T> where T: Sized {
+    /// Creates an empty vector. The first chunk is allocated on the first push.
+    #[track_caller]
+    pub fn new(arena: &Arena) -> Result<SegmentedVec<T>, UploadError> {
+        unsafe {
+            let metadata = arena.upload_no_drop::<SegmentedVecMetadata<T>>(SegmentedVecMetadata {
+                _len: 0,
+                _chunk_count: 0,
+                _chunks: [null_mut(); MAX_CHUNKS],
+            })?;
+
+            Ok(SegmentedVec {
+                _arena: arena.to_weak_arena_for::<Self>(),
+                _metadata: metadata,
+            })
+        }
+    }
+
+    /// Creates a vector by consuming an iterator.
+    #[track_caller]
+    pub fn from_iter<I: IntoIterator<Item = T>>(arena: &Arena, iter: I) -> Result<SegmentedVec<T>, UploadError> {
+        let mut items = SegmentedVec::new(arena)?;
+        for item in iter {
+            items.push(item)?;
+        }
+        Ok(items)
+    }
+
+    /// Returns the number of items if the arena is alive.
+    pub fn len(&self) -> Option<usize> {
+        if self._arena.is_alive() {
+            Some(unsafe { (*self._metadata)._len })
+        } else {
+            None
+        }
+    }
+
+    /// Returns true if arena is dead or vector is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len().unwrap_or(0) == 0
+    }
+
+    /// Returns the number of items in the allocated chunks if the arena is alive.
+    pub fn capacity(&self) -> Option<usize> {
+        if self._arena.is_alive() {
+            Some(items_before_chunk(unsafe { (*self._metadata)._chunk_count }))
+        } else {
+            None
+        }
+    }
+
+    /// Returns a guarded reference to this vector if the arena is alive. The guard keeps the arena
+    /// alive until it is dropped, so the items can be safely accessed over it.
+    pub fn get(&self) -> Option<Guard<'_, SegmentedVec<T>>> {
+        let arena = self._arena.arena()?;
+        Some(Guard::new(arena, self))
+    }
+
+    /// Returns a guarded mutable reference to this vector if the arena is alive. The guard keeps the arena
+    /// alive until it is dropped, so the items can be safely accessed over it.
+    pub fn get_mut(&mut self) -> Option<GuardMut<'_, SegmentedVec<T>>> {
+        let arena = self._arena.arena()?;
+        Some(GuardMut::new(arena, self))
+    }
+
+    /// Appends the item to the end of the vector, allocating a new chunk if the last one is full.
+    ///
+    /// Returns an error if the arena is dead or the item can not be placed in arena.
+    pub fn push(&mut self, item: T) -> Result<(), UploadError> {
+        let arena = self._arena.arena().ok_or(UploadError::ArenaIsNotAlive)?;
+        unsafe {
+            let meta = &mut *self._metadata;
+            let (chunk, offset) = chunk_and_offset(meta._len);
+            if chunk == meta._chunk_count {
+                let chunk_ptr = arena.alloc_no_drop_items_aligned_uninit::<T>(1 << chunk, std::mem::size_of::<T>())?;
+                let chunk_drop = arena.upload_no_drop(ChunkDrop { metadata: self._metadata, chunk })?;
+                arena.push_custom_drop_fn(drop_chunk::<T>, chunk_drop as *const u8)?;
+                meta._chunks[chunk] = chunk_ptr;
+                meta._chunk_count += 1;
+            }
+            std::ptr::write(meta._chunks[chunk].add(offset), item);
+            meta._len += 1;
+        }
+        Ok(())
+    }
+
+    /// Removes and returns the last item. The chunk stays allocated for the following pushes.
+    pub fn pop(&mut self) -> Option<T> {
+        if !self._arena.is_alive() {
+            return None;
+        }
+        unsafe {
+            let meta = &mut *self._metadata;
+            if meta._len == 0 {
+                return None;
+            }
+            meta._len -= 1;
+            let (chunk, offset) = chunk_and_offset(meta._len);
+            Some(std::ptr::read(meta._chunks[chunk].add(offset)))
+        }
+    }
+
+    /// Returns a reference to the item at `index` if the arena is alive and the index is in bounds.
+    pub fn get_at(&self, index: usize) -> Option<&T> {
+        if index >= self.len()? {
+            return None;
+        }
+        let (chunk, offset) = chunk_and_offset(index);
+        unsafe { Some(&*(*self._metadata)._chunks[chunk].add(offset)) }
+    }
+
+    /// Returns a mutable reference to the item at `index` if the arena is alive and the index is in bounds.
+    pub fn get_at_mut(&mut self, index: usize) -> Option<&mut T> {
+        if index >= self.len()? {
+            return None;
+        }
+        let (chunk, offset) = chunk_and_offset(index);
+        unsafe { Some(&mut *(*self._metadata)._chunks[chunk].add(offset)) }
+    }
+
+    /// Iterates over the items of every chunk as slices, returns no slices if the arena is dead.
+    pub fn chunks(&self) -> impl Iterator<Item=&[T]> {
+        let len = self.len().unwrap_or(0);
+        let metadata = self._metadata;
+        (0..MAX_CHUNKS)
+            .take_while(move |chunk| items_before_chunk(*chunk) < len)
+            .map(move |chunk| unsafe {
+                let count = (len - items_before_chunk(chunk)).min(1 << chunk);
+                std::slice::from_raw_parts((*metadata)._chunks[chunk] as *const T, count)
+            })
+    }
+
+    /// Iterates over the mutable items of every chunk as slices, returns no slices if the arena is dead.
+    pub fn chunks_mut(&mut self) -> impl Iterator<Item=&mut [T]> {
+        let len = self.len().unwrap_or(0);
+        let metadata = self._metadata;
+        (0..MAX_CHUNKS)
+            .take_while(move |chunk| items_before_chunk(*chunk) < len)
+            .map(move |chunk| unsafe {
+                let count = (len - items_before_chunk(chunk)).min(1 << chunk);
+                std::slice::from_raw_parts_mut((*metadata)._chunks[chunk], count)
+            })
+    }
+
+    /// Iterates over the item references, returns no items if the arena is dead.
+    pub fn iter(&self) -> impl Iterator<Item=&T> {
+        self.chunks().flatten()
+    }
+
+    /// Iterates over the mutable item references, returns no items if the arena is dead.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item=&mut T> {
+        self.chunks_mut().flatten()
+    }
+
+    /// Copies data to Vec
+    pub fn to_vec(&self) -> Vec<T>
+    where
+        T: Clone,
+    {
+        self.iter().cloned().collect()
+    }
+}
+
+impl<T> Index<usize> for SegmentedVec<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        self.get_at(index).expect("index out of bounds")
+    }
+}
+
+impl<T> IndexMut<usize> for SegmentedVec<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        self.get_at_mut(index).expect("index out of bounds")
+    }
+}
+
+impl<T> std::fmt::Debug for SegmentedVec<T> where T: std::fmt::Debug {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<T> PartialEq for SegmentedVec<T> where T: PartialEq {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self.iter().zip(other.iter()).all(|(l, r)| l == r)
+    }
+}
+
+#[cfg(test)]
+mod segmented_vec_tests {
+    use super::chunk_and_offset;
+    use crate::{Memory, Arena, SegmentedVec, UploadError};
+    use crate::dropflag::{Droppable, DropFlag};
+    use std::cell::RefCell;
+
+    #[test]
+    fn index_maps_to_chunk_and_offset() {
+        assert_eq!((0, 0), chunk_and_offset(0));
+        assert_eq!((1, 0), chunk_and_offset(1));
+        assert_eq!((1, 1), chunk_and_offset(2));
+        assert_eq!((2, 0), chunk_and_offset(3));
+        assert_eq!((2, 3), chunk_and_offset(6));
+        assert_eq!((3, 0), chunk_and_offset(7));
+    }
+
+    #[test]
+    fn addresses_are_stable() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let mut items = SegmentedVec::from_iter(&arena, 0..10u64).unwrap();
+        let addresses: Vec<_> = items.iter().map(|v| v as *const u64).collect();
+        for i in 10..1000 {
+            items.push(i).unwrap();
+        }
+        assert_eq!(Some(1023), items.capacity());
+        assert_eq!(addresses, items.iter().take(10).map(|v| v as *const u64).collect::<Vec<_>>());
+        assert_eq!((0..1000).collect::<Vec<u64>>(), items.to_vec());
+        assert_eq!(vec![1, 2, 4, 8], items.chunks().take(4).map(|c| c.len()).collect::<Vec<_>>());
+        assert_eq!(489, items.chunks().last().unwrap().len());
+
+        items[500] = 5;
+        assert_eq!(Some(&5), items.get_at(500));
+        assert_eq!(None, items.get_at(1000));
+        assert_eq!(Some(999), items.pop());
+        items.push(1).unwrap();
+        assert_eq!(Some(&1), items.get_at(999));
+    }
+
+    #[test]
+    fn drops_items_in_all_chunks() {
+        let mem = Memory::new();
+        let arena = Arena::new(&mem).unwrap();
+        let flags: Vec<_> = (0..6).map(|_| DropFlag::new(RefCell::new(false))).collect();
+        let mut items = SegmentedVec::from_iter(&arena, flags.iter().map(|f| Droppable { dropflag: f.clone() })).unwrap();
+        drop(items.pop());
+        assert!(*flags[5].borrow());
+        assert!(!*flags[4].borrow());
+
+        drop(arena);
+        assert!(flags.iter().all(|flag| *flag.borrow()));
+        assert_eq!(None, items.len());
+        assert_eq!(0, items.iter().count());
+        assert!(matches!(items.push(Droppable { dropflag: flags[0].clone() }), Err(UploadError::ArenaIsNotAlive)));
+    }
+}
@@ -0,0 +1,237 @@
+use crate::{Arena, WeakArena, UStrError, Guard};
+use crate::ustr::{check_nul_terminated, upload_nul_terminated};
+use std::ffi::{CStr, OsStr};
+use std::fmt::Debug;
+use std::path::Path;
+
+/// Bytes that do not contain nul values, and are stored with nul termination
+/// for easy conversion to CStr. Unlike `UStr`, the bytes do not have to be valid UTF-8.
+///
+/// This value is valid even when `Arena` is dropped, same as `UStr`.
+pub struct UBytes {
+    _arena: WeakArena,
+    byte_count_without_nul: u32,
+    first: *mut u8,
+}
+
+impl UBytes {
+    /// Initialize from bytes.
+    #[track_caller]
+    pub fn from_bytes(arena: &Arena, value: &[u8]) -> Result<UBytes, UStrError> {
+        check_nul_terminated(value)?;
+        Ok(UBytes {
+            _arena: arena.to_weak_arena_for::<Self>(),
+            byte_count_without_nul: value.len() as u32,
+            first: unsafe { upload_nul_terminated(arena, value)? },
+        })
+    }
+
+    /// Initialize from &CStr.
+    #[track_caller]
+    pub fn from_cstr(arena: &Arena, value: &CStr) -> Result<UBytes, UStrError> {
+        UBytes::from_bytes(arena, value.to_bytes())
+    }
+
+    /// Returns guarded bytes if the arena is alive. The guard keeps the arena alive until it is dropped.
+    pub fn get(&self) -> Option<Guard<'_, [u8]>> {
+        let arena = self._arena.arena()?;
+        Some(Guard::new(arena, AsRef::<[u8]>::as_ref(self)))
+    }
+
+    /// Get pointer to `char*`.
+    pub fn as_ptr(&self) -> *const i8 {
+        self.first as *const i8
+    }
+}
+
+impl Clone for UBytes {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        UBytes {
+            _arena: self._arena.clone(),
+            byte_count_without_nul: self.byte_count_without_nul,
+            first: self.first,
+        }
+    }
+}
+
+impl AsRef<[u8]> for UBytes {
+    fn as_ref(&self) -> &[u8] {
+        // potential access to weak arena
+        // but memory is returned only when the weak reference is dropped, so this is ok
+        unsafe { std::slice::from_raw_parts(self.first, self.byte_count_without_nul as usize) }
+    }
+}
+
+impl AsRef<CStr> for UBytes {
+    fn as_ref(&self) -> &CStr {
+        // potential access to weak arena
+        // but memory is returned only when the weak reference is dropped, so this is ok
+        unsafe { CStr::from_ptr(self.first as *const i8) }
+    }
+}
+
+impl Debug for UBytes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(AsRef::<CStr>::as_ref(self), f)
+    }
+}
+
+impl PartialEq for UBytes {
+    fn eq(&self, other: &Self) -> bool {
+        if self.first == other.first {
+            return true;
+        }
+        AsRef::<[u8]>::as_ref(self).eq(AsRef::<[u8]>::as_ref(other))
+    }
+}
+
+impl PartialEq<[u8]> for UBytes {
+    fn eq(&self, other: &[u8]) -> bool {
+        AsRef::<[u8]>::as_ref(self).eq(other)
+    }
+}
+
+impl Eq for UBytes {}
+
+/// Platform string that does not contain nul values, and is stored with nul termination.
+///
+/// The string is stored in the platform encoding returned by `OsStr::as_encoded_bytes`,
+/// so on Unix the pointer returned by `as_ptr` can be passed to C APIs as `char*`.
+#[derive(PartialEq, Eq)]
+pub struct UOsStr {
+    bytes: UBytes,
+}
+
+impl UOsStr {
+    /// Initialize from &OsStr.
+    #[track_caller]
+    pub fn from_os_str(arena: &Arena, value: &OsStr) -> Result<UOsStr, UStrError> {
+        Ok(UOsStr {
+            bytes: UBytes::from_bytes(arena, value.as_encoded_bytes())?,
+        })
+    }
+
+    /// Returns a guarded string if the arena is alive. The guard keeps the arena alive until it is dropped.
+    pub fn get(&self) -> Option<Guard<'_, OsStr>> {
+        let arena = self.bytes._arena.arena()?;
+        Some(Guard::new(arena, AsRef::<OsStr>::as_ref(self)))
+    }
+
+    /// Get pointer to the nul terminated string in the platform encoding.
+    pub fn as_ptr(&self) -> *const i8 {
+        self.bytes.as_ptr()
+    }
+}
+
+impl Clone for UOsStr {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        UOsStr {
+            bytes: self.bytes.clone(),
+        }
+    }
+}
+
+impl AsRef<OsStr> for UOsStr {
+    fn as_ref(&self) -> &OsStr {
+        // the bytes were returned by `as_encoded_bytes`
+        unsafe { OsStr::from_encoded_bytes_unchecked(AsRef::<[u8]>::as_ref(&self.bytes)) }
+    }
+}
+
+impl Debug for UOsStr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(AsRef::<OsStr>::as_ref(self), f)
+    }
+}
+
+/// File system path that does not contain nul values, and is stored with nul termination.
+#[derive(PartialEq, Eq)]
+pub struct UPath {
+    os_str: UOsStr,
+}
+
+impl UPath {
+    /// Initialize from &Path.
+    #[track_caller]
+    pub fn from_path(arena: &Arena, value: &Path) -> Result<UPath, UStrError> {
+        Ok(UPath {
+            os_str: UOsStr::from_os_str(arena, value.as_os_str())?,
+        })
+    }
+
+    /// Returns a guarded path if the arena is alive. The guard keeps the arena alive until it is dropped.
+    pub fn get(&self) -> Option<Guard<'_, Path>> {
+        let arena = self.os_str.bytes._arena.arena()?;
+        Some(Guard::new(arena, AsRef::<Path>::as_ref(self)))
+    }
+
+    /// Get pointer to the nul terminated path in the platform encoding.
+    pub fn as_ptr(&self) -> *const i8 {
+        self.os_str.as_ptr()
+    }
+}
+
+impl Clone for UPath {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        UPath {
+            os_str: self.os_str.clone(),
+        }
+    }
+}
+
+impl AsRef<Path> for UPath {
+    fn as_ref(&self) -> &Path {
+        Path::new(AsRef::<OsStr>::as_ref(&self.os_str))
+    }
+}
+
+impl AsRef<OsStr> for UPath {
+    fn as_ref(&self) -> &OsStr {
+        AsRef::<OsStr>::as_ref(&self.os_str)
+    }
+}
+
+impl Debug for UPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(AsRef::<Path>::as_ref(self), f)
+    }
+}
+
+#[cfg(test)]
+mod ubytes_tests {
+    use crate::{Memory, Arena, UBytes, UOsStr, UPath, UStrError};
+    use std::ffi::{CStr, CString, OsStr};
+    use std::path::Path;
+
+    #[test]
+    fn bytes_do_not_have_to_be_utf8() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let value = CString::new(vec![b'a', 0xff, b'b']).unwrap();
+        let bytes = UBytes::from_cstr(&arena, &value).unwrap();
+
+        assert_eq!(&[b'a', 0xff, b'b'][..], AsRef::<[u8]>::as_ref(&bytes));
+        assert_eq!(value.as_c_str(), AsRef::<CStr>::as_ref(&bytes));
+        assert!(matches!(UBytes::from_bytes(&arena, b"a\0b"), Err(UStrError::StrContainsNul)));
+    }
+
+    #[test]
+    fn paths_are_nul_terminated() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let path = UPath::from_path(&arena, Path::new("/tmp/file.txt")).unwrap();
+        let os_str = UOsStr::from_os_str(&arena, OsStr::new("name")).unwrap();
+
+        assert_eq!(Path::new("/tmp/file.txt"), AsRef::<Path>::as_ref(&path));
+        assert_eq!(Some("file.txt"), path.get().unwrap().file_name().and_then(|v| v.to_str()));
+        assert_eq!(CString::new("/tmp/file.txt").unwrap().as_c_str(), unsafe { CStr::from_ptr(path.as_ptr()) });
+        assert_eq!(OsStr::new("name"), AsRef::<OsStr>::as_ref(&os_str));
+
+        drop(arena);
+        assert!(path.get().is_none());
+        assert_eq!(Path::new("/tmp/file.txt"), AsRef::<Path>::as_ref(&path));
+    }
+}
@@ -1,7 +1,8 @@
-use crate::{Arena, WeakArena, UploadError};
+use crate::{Arena, WeakArena, UploadError, Guard};
 use std::ffi::{CStr, CString};
 use std::str::Utf8Error;
 use std::fmt::{Display, Debug};
+use std::ops::{Bound, RangeBounds};
 use std::error::Error;
 
 /// Error creating or uploading universal string.
@@ -32,19 +33,31 @@ impl From<UploadError> for UStrError {
 
 impl Error for UStrError {}
 
-const MAX_USTR: usize = u16::MAX as usize - 1;
+pub(crate) const MAX_USTR: usize = u32::MAX as usize - 1;
 
 /// UTF-8 string that does not contain nul values, and is stored with nul termination
 /// for easy conversion to CStr.
 ///
+/// Strings that are longer than the arena block are placed to a dedicated block.
+///
 /// This string is valid even when `Arena` is dropped, because it holds a weak arena reference
 /// which does not return memory back to `Memory` as long as it is alive. That said, make sure to
 /// drop all these strings to reclaim the memory.
-#[derive(Clone)]
 pub struct UStr {
-    _arena: WeakArena,
-    byte_count_without_nul: u16,
-    first: *mut u8,
+    pub(crate) _arena: WeakArena,
+    pub(crate) byte_count_without_nul: u32,
+    pub(crate) first: *mut u8,
+}
+
+impl Clone for UStr {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        UStr {
+            _arena: self._arena.clone(),
+            byte_count_without_nul: self.byte_count_without_nul,
+            first: self.first,
+        }
+    }
 }
 
 impl Debug for UStr {
@@ -102,6 +115,7 @@ impl Eq for UStr {}
 
 impl UStr {
     /// Initialize from &CStr.
+    #[track_caller]
     pub fn from_cstr(arena: &Arena, value: &CStr) -> Result<UStr, UStrError> {
         match value.to_str() {
             Ok(str) => {
@@ -114,12 +128,24 @@ impl UStr {
         }
     }
 
+    /// Initialize from &CStr, replacing invalid UTF-8 sequences with `U+FFFD REPLACEMENT CHARACTER`.
+    #[track_caller]
+    pub fn from_cstr_lossy(arena: &Arena, value: &CStr) -> Result<UStr, UStrError> {
+        let str = value.to_string_lossy();
+        if str.len() > MAX_USTR {
+            return Err(UStrError::StringIsTooLong { length: str.len(), max_size: MAX_USTR });
+        }
+        Ok(unsafe { UStr::from_str_unchecked(arena, &str)? })
+    }
+
     /// Initialize from `char*` which comes from a trusted source.
+    #[track_caller]
     pub fn from_trusted_cstr_ptr(arena: &Arena, bytes: *const i8) -> Result<UStr, UStrError> {
         UStr::from_cstr(arena, unsafe { CStr::from_ptr(bytes) })
     }
 
     /// Initialize from &str.
+    #[track_caller]
     pub fn from_str(arena: &Arena, value: &str) -> Result<UStr, UStrError> {
         if value.len() > MAX_USTR {
             return Err(UStrError::StringIsTooLong { length: value.len(), max_size: MAX_USTR });
@@ -134,26 +160,185 @@ impl UStr {
         Ok(unsafe { UStr::from_str_unchecked(arena, value)? })
     }
 
+    #[track_caller]
     unsafe fn from_str_unchecked(arena: &Arena, value: &str) -> Result<UStr, UploadError> {
         let bytes = value.as_bytes();
-        let cstr_with_nul_len = bytes.len() + 1;
-        let ptr = arena.upload_no_drop_bytes(cstr_with_nul_len, bytes
+        Ok(UStr {
+            _arena: arena.to_weak_arena_for::<Self>(),
+            byte_count_without_nul: bytes.len() as u32,
+            first: upload_nul_terminated(arena, bytes)?,
+        })
+    }
+
+    /// Initialize from concatenated string slices, uploaded to arena at once.
+    #[track_caller]
+    pub fn concat(arena: &Arena, values: &[&str]) -> Result<UStr, UStrError> {
+        let len = values.iter().map(|v| v.len()).sum();
+        if len > MAX_USTR {
+            return Err(UStrError::StringIsTooLong { length: len, max_size: MAX_USTR });
+        }
+        if values.iter().any(|v| v.bytes().any(|byte| byte == b'\0')) {
+            return Err(UStrError::StrContainsNul);
+        }
+
+        let ptr = unsafe { arena.upload_no_drop_bytes(len + 1, values
             .iter()
-            .map(|v| *v)
-            .chain(std::iter::once(0u8)))?;
+            .flat_map(|v| v.bytes())
+            .chain(std::iter::once(0u8)))? };
         Ok(UStr {
-            _arena: arena.to_weak_arena(),
-            byte_count_without_nul: bytes.len() as u16,
+            _arena: arena.to_weak_arena_for::<Self>(),
+            byte_count_without_nul: len as u32,
             first: ptr,
         })
     }
 
+    /// Returns the string that starts at the `start` byte and shares the memory with this string.
+    ///
+    /// Returns `None` if the `start` is out of bounds or is not on a char boundary.
+    #[track_caller]
+    pub fn suffix(&self, start: usize) -> Option<UStr> {
+        if !AsRef::<str>::as_ref(self).is_char_boundary(start) {
+            return None;
+        }
+        Some(UStr {
+            _arena: self._arena.clone(),
+            byte_count_without_nul: self.byte_count_without_nul - start as u32,
+            first: unsafe { self.first.add(start) },
+        })
+    }
+
+    /// Returns the part of this string in the byte `range`, that shares the memory with this string.
+    ///
+    /// Returns `None` if the `range` is out of bounds or is not on a char boundary.
+    #[track_caller]
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Option<UStrSlice> {
+        let start = match range.start_bound() {
+            Bound::Included(start) => *start,
+            Bound::Excluded(start) => start.checked_add(1)?,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(end) => end.checked_add(1)?,
+            Bound::Excluded(end) => *end,
+            Bound::Unbounded => self.byte_count_without_nul as usize,
+        };
+        AsRef::<str>::as_ref(self).get(start..end)?;
+        Some(UStrSlice {
+            _arena: self._arena.clone(),
+            byte_count: (end - start) as u32,
+            first: unsafe { self.first.add(start) },
+        })
+    }
+
+    /// Copies this string to another arena, so that this string can be dropped and stop holding
+    /// the memory of its arena. Returns a clone if the string is already in the `arena`.
+    #[track_caller]
+    pub fn relocate_to(&self, arena: &Arena) -> Result<UStr, UStrError> {
+        if self._arena.belongs_to(arena) {
+            return Ok(self.clone());
+        }
+        Ok(unsafe { UStr::from_str_unchecked(arena, self.as_ref())? })
+    }
+
+    /// Returns a guarded string if the arena is alive. The guard keeps the arena alive until it is dropped.
+    pub fn get(&self) -> Option<Guard<'_, str>> {
+        let arena = self._arena.arena()?;
+        Some(Guard::new(arena, AsRef::<str>::as_ref(self)))
+    }
+
     /// Get pointer to `char*`.
     pub fn as_ptr(&self) -> *const i8 {
         self.first as *const i8
     }
 }
 
+/// Part of `UStr` that shares the memory with it. Unlike `UStr`, it is not nul terminated,
+/// so it is available as `&str` only.
+pub struct UStrSlice {
+    _arena: WeakArena,
+    byte_count: u32,
+    first: *mut u8,
+}
+
+impl UStrSlice {
+    /// Returns a guarded string if the arena is alive. The guard keeps the arena alive until it is dropped.
+    pub fn get(&self) -> Option<Guard<'_, str>> {
+        let arena = self._arena.arena()?;
+        Some(Guard::new(arena, AsRef::<str>::as_ref(self)))
+    }
+}
+
+impl Clone for UStrSlice {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        UStrSlice {
+            _arena: self._arena.clone(),
+            byte_count: self.byte_count,
+            first: self.first,
+        }
+    }
+}
+
+impl AsRef<str> for UStrSlice {
+    fn as_ref(&self) -> &str {
+        // potential access to weak arena
+        // but memory is returned only when the weak reference is dropped, so this is ok
+        let slice = unsafe { std::slice::from_raw_parts(self.first, self.byte_count as usize) };
+        unsafe { std::str::from_utf8_unchecked(slice) }
+    }
+}
+
+impl Debug for UStrSlice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(AsRef::<str>::as_ref(self), f)
+    }
+}
+
+impl Display for UStrSlice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(AsRef::<str>::as_ref(self), f)
+    }
+}
+
+impl PartialEq for UStrSlice {
+    fn eq(&self, other: &Self) -> bool {
+        AsRef::<str>::as_ref(self).eq(AsRef::<str>::as_ref(other))
+    }
+}
+
+impl PartialEq<str> for UStrSlice {
+    fn eq(&self, other: &str) -> bool {
+        AsRef::<str>::as_ref(self).eq(other)
+    }
+}
+
+impl PartialEq<UStrSlice> for str {
+    fn eq(&self, other: &UStrSlice) -> bool {
+        self.eq(AsRef::<str>::as_ref(other))
+    }
+}
+
+impl Eq for UStrSlice {}
+
+/// Checks that the bytes can be stored as nul terminated string.
+pub(crate) fn check_nul_terminated(bytes: &[u8]) -> Result<(), UStrError> {
+    if bytes.len() > MAX_USTR {
+        return Err(UStrError::StringIsTooLong { length: bytes.len(), max_size: MAX_USTR });
+    }
+    if bytes.contains(&b'\0') {
+        return Err(UStrError::StrContainsNul);
+    }
+    Ok(())
+}
+
+/// Uploads the bytes followed by nul to arena and returns the pointer to the first byte.
+pub(crate) unsafe fn upload_nul_terminated(arena: &Arena, bytes: &[u8]) -> Result<*mut u8, UploadError> {
+    arena.upload_no_drop_bytes(bytes.len() + 1, bytes
+        .iter()
+        .map(|v| *v)
+        .chain(std::iter::once(0u8)))
+}
+
 impl AsRef<str> for UStr {
     fn as_ref(&self) -> &str {
         // potential access to weak arena
@@ -173,9 +358,9 @@ impl AsRef<CStr> for UStr {
 
 #[cfg(test)]
 mod ustr_tests {
-    use crate::{Memory, Arena};
+    use crate::{Memory, Arena, WeakArena};
     use crate::UStr;
-    use std::ffi::CString;
+    use std::ffi::{CStr, CString};
 
     #[test]
     fn test_str() {
@@ -192,4 +377,72 @@ mod ustr_tests {
         let arena = Arena::new(&memory).unwrap();
         assert_eq!(None, UStr::from_str(&arena, "hello\0world!").ok());
     }
+
+    #[test]
+    fn test_str_views_share_memory() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let str = UStr::concat(&arena, &["hello", " ", "wörld"]).unwrap();
+        assert_eq!("hello wörld", &str);
+
+        let suffix = str.suffix(6).unwrap();
+        assert_eq!("wörld", &suffix);
+        assert_eq!(unsafe { str.as_ptr().add(6) }, suffix.as_ptr());
+        assert_eq!(&CString::new("wörld").unwrap(), &suffix);
+        assert_eq!("", &str.suffix(12).unwrap());
+        assert!(str.suffix(8).is_none(), "not on char boundary");
+        assert!(str.suffix(13).is_none());
+
+        assert_eq!("hello", &str.slice(..5).unwrap());
+        assert_eq!("wö", &str.slice(6..=8).unwrap());
+        assert!(str.slice(6..8).is_none(), "not on char boundary");
+        assert!(str.slice(3..20).is_none());
+        assert!(UStr::concat(&arena, &["a", "\0"]).is_err());
+    }
+
+    #[test]
+    fn test_str_relocated_from_dropped_arena() {
+        let memory = Memory::new();
+        let long_lived = Arena::new(&memory).unwrap();
+        let short_lived = Arena::new(&memory).unwrap();
+        let str = UStr::from_str(&short_lived, "survivor").unwrap();
+        let _other = UStr::from_str(&short_lived, "other").unwrap();
+        assert_eq!(2, short_lived.retention().weak_handles);
+        assert_eq!(1, short_lived.retention().blocks);
+
+        let relocated = str.relocate_to(&long_lived).unwrap();
+        assert_ne!(str.as_ptr(), relocated.as_ptr());
+        assert_eq!(relocated.as_ptr(), relocated.relocate_to(&long_lived).unwrap().as_ptr());
+        drop(str);
+        drop(_other);
+        assert_eq!(0, short_lived.retention().weak_handles);
+
+        drop(short_lived);
+        assert_eq!("survivor", &relocated);
+    }
+
+    #[test]
+    fn test_str_from_invalid_cstr() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let value = CString::new(vec![b'a', 0xff, b'b']).unwrap();
+        assert!(UStr::from_cstr(&arena, &value).is_err());
+        assert_eq!("a\u{FFFD}b", &UStr::from_cstr_lossy(&arena, &value).unwrap());
+    }
+
+    #[test]
+    fn test_str_longer_than_block() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let short = UStr::from_str(&arena, "short").unwrap();
+        let long_value = "0123456789".repeat(100_000);
+        let long = UStr::from_str(&arena, &long_value).unwrap();
+        let after = UStr::from_str(&arena, "after").unwrap();
+
+        assert_eq!(std::mem::size_of::<WeakArena>() + 16, std::mem::size_of::<UStr>(), "handle did not grow");
+        assert_eq!(long_value.as_str(), &long);
+        assert_eq!(long_value.len(), AsRef::<CStr>::as_ref(&long).to_bytes().len());
+        assert_eq!("short", &short);
+        assert_eq!("after", &after);
+    }
 }
\ No newline at end of file
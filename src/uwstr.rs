@@ -0,0 +1,155 @@
+use crate::{Arena, WeakArena, UploadError, UStr, UStrError, Guard};
+use crate::ustr::MAX_USTR;
+use std::fmt::{Debug, Display};
+
+/// UTF-16 string that does not contain nul values, and is stored with nul termination
+/// for passing to APIs that expect `const u16*` wide strings.
+///
+/// This string is valid even when `Arena` is dropped, same as `UStr`.
+pub struct UWStr {
+    _arena: WeakArena,
+    unit_count_without_nul: u32,
+    first: *mut u16,
+}
+
+impl UWStr {
+    /// Initialize from &str.
+    #[track_caller]
+    pub fn from_str(arena: &Arena, value: &str) -> Result<UWStr, UStrError> {
+        if value.bytes().any(|byte| byte == b'\0') {
+            return Err(UStrError::StrContainsNul);
+        }
+        let len = value.encode_utf16().count();
+        if len > MAX_USTR {
+            return Err(UStrError::StringIsTooLong { length: len, max_size: MAX_USTR });
+        }
+        Ok(unsafe { UWStr::from_units_unchecked(arena, len, value.encode_utf16())? })
+    }
+
+    /// Initialize from UTF-16 code units, which do not have to be valid UTF-16.
+    #[track_caller]
+    pub fn from_wide(arena: &Arena, value: &[u16]) -> Result<UWStr, UStrError> {
+        if value.contains(&0) {
+            return Err(UStrError::StrContainsNul);
+        }
+        if value.len() > MAX_USTR {
+            return Err(UStrError::StringIsTooLong { length: value.len(), max_size: MAX_USTR });
+        }
+        Ok(unsafe { UWStr::from_units_unchecked(arena, value.len(), value.iter().copied())? })
+    }
+
+    /// Initialize from `UStr`.
+    #[track_caller]
+    pub fn from_ustr(arena: &Arena, value: &UStr) -> Result<UWStr, UStrError> {
+        UWStr::from_str(arena, value.as_ref())
+    }
+
+    #[track_caller]
+    unsafe fn from_units_unchecked(arena: &Arena, len: usize, units: impl Iterator<Item=u16>) -> Result<UWStr, UploadError> {
+        let first = arena.alloc_no_drop_items_aligned_uninit::<u16>(len + 1, std::mem::size_of::<u16>())?;
+        for (i, unit) in units.take(len).enumerate() {
+            *first.add(i) = unit;
+        }
+        *first.add(len) = 0;
+        Ok(UWStr {
+            _arena: arena.to_weak_arena_for::<Self>(),
+            unit_count_without_nul: len as u32,
+            first,
+        })
+    }
+
+    /// Converts to `UStr`, replacing invalid UTF-16 with `U+FFFD REPLACEMENT CHARACTER`.
+    #[track_caller]
+    pub fn to_ustr(&self, arena: &Arena) -> Result<UStr, UStrError> {
+        UStr::from_str(arena, &self.to_string_lossy())
+    }
+
+    /// Converts to `String`, replacing invalid UTF-16 with `U+FFFD REPLACEMENT CHARACTER`.
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf16_lossy(self.as_wide())
+    }
+
+    /// Returns guarded UTF-16 code units if the arena is alive. The guard keeps the arena alive until it is dropped.
+    pub fn get(&self) -> Option<Guard<'_, [u16]>> {
+        let arena = self._arena.arena()?;
+        Some(Guard::new(arena, self.as_wide()))
+    }
+
+    /// Returns UTF-16 code units without the nul terminator.
+    pub fn as_wide(&self) -> &[u16] {
+        // potential access to weak arena
+        // but memory is returned only when the weak reference is dropped, so this is ok
+        unsafe { std::slice::from_raw_parts(self.first, self.unit_count_without_nul as usize) }
+    }
+
+    /// Get pointer to `const u16*`.
+    pub fn as_ptr(&self) -> *const u16 {
+        self.first
+    }
+}
+
+impl Clone for UWStr {
+    #[track_caller]
+    fn clone(&self) -> Self {
+        UWStr {
+            _arena: self._arena.clone(),
+            unit_count_without_nul: self.unit_count_without_nul,
+            first: self.first,
+        }
+    }
+}
+
+impl Debug for UWStr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Debug::fmt(&self.to_string_lossy(), f)
+    }
+}
+
+impl Display for UWStr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(&self.to_string_lossy(), f)
+    }
+}
+
+impl PartialEq for UWStr {
+    fn eq(&self, other: &Self) -> bool {
+        if self.first == other.first {
+            return true;
+        }
+        self.as_wide().eq(other.as_wide())
+    }
+}
+
+impl Eq for UWStr {}
+
+#[cfg(test)]
+mod uwstr_tests {
+    use crate::{Memory, Arena, UStr, UWStr, UStrError};
+
+    #[test]
+    fn test_wide_str() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        let value = UWStr::from_str(&arena, "héllo 🌍").unwrap();
+
+        let expected: Vec<u16> = "héllo 🌍".encode_utf16().collect();
+        assert_eq!(&expected[..], value.as_wide());
+        assert_eq!(0, unsafe { *value.as_ptr().add(expected.len()) }, "nul terminated");
+        assert_eq!("héllo 🌍", value.to_string_lossy());
+
+        let ustr = value.to_ustr(&arena).unwrap();
+        assert_eq!(UStr::from_str(&arena, "héllo 🌍").unwrap(), ustr);
+        assert_eq!(value, UWStr::from_ustr(&arena, &ustr).unwrap());
+    }
+
+    #[test]
+    fn test_wide_str_with_nul_or_invalid_units() {
+        let memory = Memory::new();
+        let arena = Arena::new(&memory).unwrap();
+        assert!(matches!(UWStr::from_str(&arena, "a\0b"), Err(UStrError::StrContainsNul)));
+        assert!(matches!(UWStr::from_wide(&arena, &[97, 0]), Err(UStrError::StrContainsNul)));
+
+        let lone_surrogate = UWStr::from_wide(&arena, &[97, 0xD800]).unwrap();
+        assert_eq!("a\u{FFFD}", lone_surrogate.to_string_lossy());
+    }
+}